@@ -0,0 +1,740 @@
+//! Archives holding many compressed files and their metadata
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write, Seek, SeekFrom, ErrorKind, BufReader, BufWriter};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use glob::Pattern;
+
+use crate::container::{self, Algorithm};
+use crate::error::{truncated, Error, Result};
+use crate::fileops::{self, OutputPolicy};
+use crate::metadata::{mode_of, mtime_of, set_mode, PERMISSION_BITS};
+
+/// Bytes every archive starts with
+pub const MAGIC: [u8; 4] = *b"FCAR";
+
+/// Bytes every archive ends with
+pub const END_MAGIC: [u8; 4] = *b"FCAE";
+
+/// Newest archive version this build can read and the one it writes
+pub const VERSION: u8 = 2;
+
+/// Input size after which a new solid block is started by default
+pub const DEFAULT_SOLID_BLOCK_SIZE: u64 = 16 << 20;
+
+const HEADER_SIZE: u64 = 5;
+const FOOTER_SIZE: u64 = 16;
+
+/// A file stored in an [`Archive`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// Path inside the archive, always relative and separated by `/`
+    pub path: String,
+    /// Uncompressed size in bytes
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch
+    pub mtime: i64,
+    /// Unix permission bits
+    pub mode: u32,
+    pub algorithm: Algorithm,
+    offset: u64,
+    compressed_size: u64,
+    // Where the file starts in its decompressed block, 0 unless it is in a solid block
+    block_offset: u64,
+}
+
+impl Entry {
+    /// Returns the size of the block holding the entry inside the archive,
+    /// which is shared with the other files of a solid block
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&(self.path.len() as u16).to_be_bytes())?;
+        writer.write_all(self.path.as_bytes())?;
+        writer.write_all(&self.size.to_be_bytes())?;
+        writer.write_all(&self.mtime.to_be_bytes())?;
+        writer.write_all(&self.mode.to_be_bytes())?;
+        writer.write_all(&[self.algorithm.id()])?;
+        writer.write_all(&self.offset.to_be_bytes())?;
+        writer.write_all(&self.compressed_size.to_be_bytes())?;
+        writer.write_all(&self.block_offset.to_be_bytes())?;
+        Ok(())
+    }
+
+    fn read_from<R: Read>(reader: &mut R, version: u8) -> Result<Entry> {
+        let mut path = vec![0u8; read_array::<2, _>(reader).map(u16::from_be_bytes)? as usize];
+        reader.read_exact(&mut path)?;
+        let path = String::from_utf8(path)
+            .map_err(|_| invalid("Archive entry path is not UTF-8"))?;
+
+        let size = u64::from_be_bytes(read_array(reader)?);
+        let mtime = i64::from_be_bytes(read_array(reader)?);
+        let mode = u32::from_be_bytes(read_array(reader)?);
+        let [algorithm] = read_array(reader)?;
+        let algorithm = Algorithm::from_id(algorithm)
+            .ok_or_else(|| invalid("Unknown algorithm in archive entry"))?;
+        let offset = u64::from_be_bytes(read_array(reader)?);
+        let compressed_size = u64::from_be_bytes(read_array(reader)?);
+        // Version 1 archives have no solid blocks
+        let block_offset = match version {
+            1 => 0,
+            _ => u64::from_be_bytes(read_array(reader)?),
+        };
+
+        Ok(Entry { path, size, mtime, mode, algorithm, offset, compressed_size, block_offset })
+    }
+
+    fn from_metadata(path: String, metadata: &fs::Metadata, algorithm: Algorithm) -> Entry {
+        Entry {
+            path,
+            size: metadata.len(),
+            mtime: mtime_of(metadata).0,
+            mode: mode_of(metadata),
+            algorithm,
+            offset: 0,
+            compressed_size: 0,
+            block_offset: 0,
+        }
+    }
+}
+
+/// A multi-file archive
+///
+/// Every entry is stored in a compressed container, followed by a central
+/// directory listing all entries and a fixed size footer that points at
+/// the directory:
+///
+/// `MAGIC version | blocks ... | directory | dir offset, entry count, END_MAGIC`
+///
+/// A block normally holds a single file. Files added in solid mode are
+/// concatenated into shared blocks instead, and each entry records where
+/// it starts in the decompressed block
+///
+/// New blocks are appended after the old directory and footer, which stay
+/// valid until the new ones are written, so an add that fails leaves the
+/// archive as it was. The old directory is left behind as a gap until
+/// [`Archive::delete`] compacts the archive
+pub struct Archive {
+    file: File,
+    entries: Vec<Entry>,
+}
+
+impl Archive {
+    /// Creates a new empty archive, or follows `policy` if `path` exists
+    pub fn create(path: &Path, policy: OutputPolicy) -> Result<Archive> {
+        let mut file = fileops::create(path, policy)?;
+        file.write_all(&MAGIC)?;
+        file.write_all(&[VERSION])?;
+        write_footer(&mut file, HEADER_SIZE, 0)?;
+
+        Archive::open(&file.commit()?)
+    }
+
+    /// Opens an existing archive and reads its directory
+    pub fn open(path: &Path) -> Result<Archive> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        let [m0, m1, m2, m3, version] = read_array(&mut file)?;
+        if [m0, m1, m2, m3] != MAGIC {
+            return Err(invalid("Not an archive (bad magic bytes)"));
+        }
+        if version == 0 || version > VERSION {
+            return Err(Error::Unsupported(format!(
+                "Unsupported archive version {} (newest supported is {})", version, VERSION
+            )));
+        }
+
+        file.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+        let dir_offset = u64::from_be_bytes(read_array(&mut file)?);
+        let count = u32::from_be_bytes(read_array(&mut file)?);
+        if read_array(&mut file)? != END_MAGIC {
+            return Err(invalid("Archive footer is missing"));
+        }
+
+        file.seek(SeekFrom::Start(dir_offset))?;
+        let mut reader = BufReader::new(&mut file);
+        let entries = (0..count)
+            .map(|_| Entry::read_from(&mut reader, version))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Archive { file, entries })
+    }
+
+    /// Returns every entry in the order they were added
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Adds a file, or every file below a directory, to the archive
+    ///
+    /// Entries are named after `path` with any root or `.` stripped.
+    /// Returns the entries that were added
+    pub fn add(&mut self, path: &Path, algorithm: Algorithm) -> Result<Vec<Entry>> {
+        let files = collect_files(path)?;
+        self.append(|archive| {
+            for file in &files {
+                archive.append_file(file, &archive_path(file)?, algorithm)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Adds a single file under the given archive path
+    pub fn add_file(&mut self, path: &Path, name: &str, algorithm: Algorithm) -> Result<Entry> {
+        let mut added = self.append(|archive| archive.append_file(path, name, algorithm))?;
+        Ok(added.remove(0))
+    }
+
+    /// Adds every file below `paths` in solid mode
+    ///
+    /// The files are concatenated and compressed as one stream so matches
+    /// can cross file boundaries. A new block is started whenever a block
+    /// holds at least `block_size` bytes, which limits how much has to be
+    /// decompressed to get at a single file. Returns the entries that were added
+    pub fn add_solid(&mut self, paths: &[&Path], algorithm: Algorithm, block_size: u64) -> Result<Vec<Entry>> {
+        let mut files: Vec<(String, PathBuf)> = Vec::new();
+        for path in paths {
+            for file in collect_files(path)? {
+                let name = archive_path(&file)?;
+                if self.contains(&name) || files.iter().any(|(other, _)| *other == name) {
+                    return Err(already_exists(&name));
+                }
+                files.push((name, file));
+            }
+        }
+
+        self.append(|archive| {
+            let mut block = Vec::new();
+            let mut pending = Vec::new();
+            for (name, path) in files {
+                let mut entry = Entry::from_metadata(name, &fs::metadata(&path)?, algorithm);
+                entry.block_offset = block.len() as u64;
+                File::open(&path)?.read_to_end(&mut block)?;
+                // The file may have changed since its metadata was read
+                entry.size = block.len() as u64 - entry.block_offset;
+                pending.push(entry);
+
+                if block.len() as u64 >= block_size {
+                    let offset = archive.file.seek(SeekFrom::End(0))?;
+                    archive.write_block(&mut &block[..], algorithm, std::mem::take(&mut pending), offset)?;
+                    block.clear();
+                }
+            }
+            if !pending.is_empty() {
+                let offset = archive.file.seek(SeekFrom::End(0))?;
+                archive.write_block(&mut &block[..], algorithm, pending, offset)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Decompresses one entry into a writer
+    pub fn read_entry<W: Write>(&mut self, entry: &Entry, writer: &mut W) -> Result<()> {
+        let mut window = Window { inner: writer, skip: entry.block_offset, left: entry.size };
+        self.read_block(entry, &mut window)
+    }
+
+    /// Extracts the entries that match `only` (or all of them) below `dest`,
+    /// restoring their modification time and permissions
+    ///
+    /// Files that already exist are handled according to `policy`.
+    /// Returns the entries that were extracted
+    pub fn extract(&mut self, dest: &Path, only: Option<&Pattern>, policy: OutputPolicy) -> Result<Vec<Entry>> {
+        let selected: Vec<Entry> = self.entries.iter()
+            .filter(|entry| only.is_none_or(|pattern| pattern.matches(&entry.path)))
+            .cloned()
+            .collect();
+
+        let mut shared: HashMap<u64, usize> = HashMap::new();
+        for entry in &selected {
+            *shared.entry(entry.offset).or_default() += 1;
+        }
+
+        // Solid blocks are decompressed once for all the files wanted from them
+        let mut cache: Option<(u64, Vec<u8>)> = None;
+        for entry in &selected {
+            let path = dest.join(safe_path(&entry.path)?);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let mut file = fileops::create(&path, policy)?;
+            {
+                let mut writer = BufWriter::new(&mut file);
+                if shared[&entry.offset] > 1 {
+                    if cache.as_ref().is_none_or(|(offset, _)| *offset != entry.offset) {
+                        let mut block = Vec::new();
+                        self.read_block(entry, &mut block)?;
+                        cache = Some((entry.offset, block));
+                    }
+                    if let Some((_, block)) = &cache {
+                        writer.write_all(block_slice(block, entry)?)?;
+                    }
+                } else {
+                    self.read_entry(entry, &mut writer)?;
+                }
+                writer.flush()?;
+            }
+            file.as_file().set_modified(UNIX_EPOCH + Duration::from_secs(entry.mtime.max(0) as u64))?;
+            // The mode comes from the archive, so setuid, setgid and sticky bits are dropped
+            set_mode(file.temp_path(), entry.mode & PERMISSION_BITS)?;
+            file.commit()?;
+        }
+
+        Ok(selected)
+    }
+
+    /// Removes every entry that matches `pattern` and compacts the archive
+    ///
+    /// Solid blocks that keep some of their files are recompressed without
+    /// the removed ones. Returns the entries that were removed
+    pub fn delete(&mut self, pattern: &Pattern) -> Result<Vec<Entry>> {
+        let (removed, kept): (Vec<Entry>, Vec<Entry>) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|entry| pattern.matches(&entry.path));
+
+        // Blocks only ever move towards the start, so nothing is
+        // overwritten before it has been copied or read
+        let mut offset = HEADER_SIZE;
+        let mut buffer = Vec::new();
+        let mut rewrites = Vec::new();
+        for group in kept.chunk_by(|a, b| a.offset == b.offset) {
+            if removed.iter().any(|entry| entry.offset == group[0].offset) {
+                let mut block = Vec::new();
+                self.read_block(&group[0], &mut block)?;
+
+                let mut data = Vec::new();
+                let mut entries = Vec::new();
+                for entry in group {
+                    let mut entry = entry.clone();
+                    let slice = block_slice(&block, &entry)?;
+                    entry.block_offset = data.len() as u64;
+                    data.extend_from_slice(slice);
+                    entries.push(entry);
+                }
+                rewrites.push((data, entries));
+                continue;
+            }
+
+            let size = group[0].compressed_size;
+            if group[0].offset != offset {
+                buffer.resize(size as usize, 0);
+                self.file.seek(SeekFrom::Start(group[0].offset))?;
+                self.file.read_exact(&mut buffer)?;
+                self.file.seek(SeekFrom::Start(offset))?;
+                self.file.write_all(&buffer)?;
+            }
+            self.entries.extend(group.iter().cloned().map(|mut entry| {
+                entry.offset = offset;
+                entry
+            }));
+            offset += size;
+        }
+
+        for (data, entries) in rewrites {
+            let algorithm = entries[0].algorithm;
+            let offset = self.directory_offset();
+            self.write_block(&mut &data[..], algorithm, entries, offset)?;
+        }
+
+        self.write_directory()?;
+        Ok(removed)
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.entries.iter().any(|entry| entry.path == name)
+    }
+
+    // Runs `add` to append blocks after the end of the file, then writes
+    // the new directory after them. If anything fails the file is cut back
+    // to its old end, where the old directory and footer still are.
+    // Returns the entries that were added
+    fn append<F>(&mut self, add: F) -> Result<Vec<Entry>>
+        where F: FnOnce(&mut Archive) -> Result<()>
+    {
+        let end = self.file.seek(SeekFrom::End(0))?;
+        let count = self.entries.len();
+
+        match add(self).and_then(|_| self.write_directory()) {
+            Ok(()) => Ok(self.entries[count..].to_vec()),
+            Err(e) => {
+                self.entries.truncate(count);
+                self.file.set_len(end)?;
+                Err(e)
+            },
+        }
+    }
+
+    // Compresses a file into its own block at the end of the archive
+    fn append_file(&mut self, path: &Path, name: &str, algorithm: Algorithm) -> Result<()> {
+        if self.contains(name) {
+            return Err(already_exists(name));
+        }
+
+        let entry = Entry::from_metadata(name.to_string(), &fs::metadata(path)?, algorithm);
+        let mut input = BufReader::new(File::open(path)?);
+        let offset = self.file.seek(SeekFrom::End(0))?;
+        self.write_block(&mut input, algorithm, vec![entry], offset)
+    }
+
+    // Compresses a block at `offset` and adds the entries stored in it
+    fn write_block<R: Read>(&mut self, reader: &mut R, algorithm: Algorithm, entries: Vec<Entry>, offset: u64) -> Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        {
+            let mut writer = BufWriter::new(&mut self.file);
+            container::compress(reader, &mut writer, algorithm)?;
+            writer.flush()?;
+        }
+        let compressed_size = self.file.stream_position()? - offset;
+
+        self.entries.extend(entries.into_iter().map(|mut entry| {
+            entry.offset = offset;
+            entry.compressed_size = compressed_size;
+            entry
+        }));
+        Ok(())
+    }
+
+    // Decompresses the whole block holding an entry
+    fn read_block<W: Write>(&mut self, entry: &Entry, writer: &mut W) -> Result<()> {
+        self.file.seek(SeekFrom::Start(entry.offset))?;
+        let mut reader = BufReader::new((&mut self.file).take(entry.compressed_size));
+        container::decompress(&mut reader, writer)?;
+        Ok(())
+    }
+
+    // Returns where the entry data ends and the directory starts
+    fn directory_offset(&self) -> u64 {
+        self.entries.last().map_or(HEADER_SIZE, |entry| entry.offset + entry.compressed_size)
+    }
+
+    // Writes the directory and footer after the last entry and drops anything after them
+    fn write_directory(&mut self) -> Result<()> {
+        let offset = self.directory_offset();
+        self.file.seek(SeekFrom::Start(offset))?;
+        {
+            let mut writer = BufWriter::new(&mut self.file);
+            for entry in &self.entries {
+                entry.write_to(&mut writer)?;
+            }
+            write_footer(&mut writer, offset, self.entries.len() as u32)?;
+            writer.flush()?;
+        }
+
+        let end = self.file.stream_position()?;
+        self.file.set_len(end)?;
+
+        // Older archives are upgraded since the directory is written in the
+        // current format, once it is there to be read
+        self.file.seek(SeekFrom::Start(MAGIC.len() as u64))?;
+        self.file.write_all(&[VERSION])?;
+        self.file.sync_all()?;
+        Ok(())
+    }
+}
+
+fn write_footer<W: Write>(writer: &mut W, dir_offset: u64, count: u32) -> Result<()> {
+    writer.write_all(&dir_offset.to_be_bytes())?;
+    writer.write_all(&count.to_be_bytes())?;
+    writer.write_all(&END_MAGIC)?;
+    Ok(())
+}
+
+// Passes on `left` bytes after skipping the first `skip` and drops the rest
+struct Window<W: Write> {
+    inner: W,
+    skip: u64,
+    left: u64,
+}
+
+impl<W: Write> Write for Window<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let skip = self.skip.min(buf.len() as u64) as usize;
+        let take = self.left.min((buf.len() - skip) as u64) as usize;
+        self.inner.write_all(&buf[skip..skip + take])?;
+        self.skip -= skip as u64;
+        self.left -= take as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Returns the part of a decompressed block that belongs to an entry
+fn block_slice<'a>(block: &'a [u8], entry: &Entry) -> Result<&'a [u8]> {
+    let start = entry.block_offset as usize;
+    block.get(start..start + entry.size as usize)
+        .ok_or_else(|| invalid("Archive entry lies outside its block"))
+}
+
+// Lists a file, or every file below a directory in name order
+fn collect_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut stack = vec![path.to_path_buf()];
+
+    while let Some(path) = stack.pop() {
+        if path.is_dir() {
+            let mut children = fs::read_dir(&path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()?;
+            // Sorted in reverse so they come off the stack in order
+            children.sort_by(|a, b| b.cmp(a));
+            stack.extend(children);
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+fn already_exists(name: &str) -> Error {
+    Error::Io(io::Error::new(ErrorKind::AlreadyExists, format!("{} is already in the archive", name)))
+}
+
+// Turns a filesystem path into an archive path
+fn archive_path(path: &Path) -> Result<String> {
+    let parts: Vec<&str> = path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_str()),
+            _ => None,
+        })
+        .collect::<Option<_>>()
+        .ok_or_else(|| Error::InvalidParams(format!("{} is not UTF-8", path.display())))?;
+
+    match parts.is_empty() {
+        true => Err(Error::InvalidParams(format!("{} has no file name", path.display()))),
+        false => Ok(parts.join("/")),
+    }
+}
+
+// Turns an archive path back into a relative path, refusing anything
+// that could escape the destination directory
+fn safe_path(name: &str) -> Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for part in name.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => return Err(invalid("Archive entry path leaves the destination")),
+            part if Path::new(part).components().count() != 1 || Path::new(part).has_root() => {
+                return Err(invalid("Archive entry path leaves the destination"));
+            },
+            part => path.push(part),
+        }
+    }
+    Ok(path)
+}
+
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes).map_err(|e| truncated(e.into()))?;
+    Ok(bytes)
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::Corrupt(msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("file_compressor_archive_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn setup(dir: &Path) -> PathBuf {
+        let input = dir.join("input");
+        fs::create_dir_all(input.join("docs")).unwrap();
+        fs::write(input.join("a.txt"), b"I AM SAM. I AM SAM. SAM I AM.").unwrap();
+        fs::write(input.join("docs/b.md"), b"# Title\n\nSome words, some words.").unwrap();
+        fs::write(input.join("docs/c.txt"), b"").unwrap();
+        input
+    }
+
+    #[test]
+    fn test_add_list_extract() {
+        let dir = scratch("extract");
+        let input = setup(&dir);
+        let archive_path = dir.join("test.fca");
+
+        {
+            let mut archive = Archive::create(&archive_path, OutputPolicy::FailIfExists).unwrap();
+            archive.add(&input.join("a.txt"), Algorithm::Lzss).unwrap();
+            archive.add(&input.join("docs"), Algorithm::Huffman).unwrap();
+        }
+
+        let mut archive = Archive::open(&archive_path).unwrap();
+        let names: Vec<&str> = archive.entries().iter().map(|entry| entry.path.as_str()).collect();
+        let base = archive_path_of(&input);
+        assert_eq!(names, vec![
+            format!("{}/a.txt", base),
+            format!("{}/docs/b.md", base),
+            format!("{}/docs/c.txt", base),
+        ]);
+        assert_eq!(archive.entries()[1].algorithm, Algorithm::Huffman);
+
+        let out = dir.join("out");
+        archive.extract(&out, None, OutputPolicy::FailIfExists).unwrap();
+        for name in ["a.txt", "docs/b.md", "docs/c.txt"] {
+            assert_eq!(
+                fs::read(out.join(&base).join(name)).unwrap(),
+                fs::read(input.join(name)).unwrap()
+            );
+        }
+
+        let only = dir.join("only");
+        let pattern = Pattern::new("**/*.md").unwrap();
+        let extracted = archive.extract(&only, Some(&pattern), OutputPolicy::FailIfExists).unwrap();
+        assert_eq!(extracted.len(), 1);
+        assert!(only.join(&base).join("docs/b.md").exists());
+        assert!(!only.join(&base).join("a.txt").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_delete() {
+        let dir = scratch("delete");
+        let input = setup(&dir);
+        let archive_path = dir.join("test.fca");
+
+        let mut archive = Archive::create(&archive_path, OutputPolicy::FailIfExists).unwrap();
+        archive.add(&input, Algorithm::Lzss).unwrap();
+        let before = fs::metadata(&archive_path).unwrap().len();
+
+        let removed = archive.delete(&Pattern::new("**/a.txt").unwrap()).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(fs::metadata(&archive_path).unwrap().len() < before);
+
+        let mut archive = Archive::open(&archive_path).unwrap();
+        assert_eq!(archive.entries().len(), 2);
+
+        let entry = archive.entries()[0].clone();
+        let mut output = Vec::new();
+        archive.read_entry(&entry, &mut output).unwrap();
+        assert_eq!(output, fs::read(input.join("docs/b.md")).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_duplicates_and_escapes() {
+        let dir = scratch("duplicates");
+        let input = setup(&dir);
+
+        let mut archive = Archive::create(&dir.join("test.fca"), OutputPolicy::FailIfExists).unwrap();
+        archive.add_file(&input.join("a.txt"), "a.txt", Algorithm::Lzss).unwrap();
+        let err = archive.add_file(&input.join("a.txt"), "a.txt", Algorithm::Lzss).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        assert!(safe_path("../evil").is_err());
+        assert!(safe_path("a/../../evil").is_err());
+        assert_eq!(safe_path("a/./b").unwrap(), Path::new("a/b"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_solid() {
+        let dir = scratch("solid");
+        let input = dir.join("input");
+        fs::create_dir_all(&input).unwrap();
+        for i in 0..20 {
+            let text = format!("[package]\nname = \"crate{}\"\nversion = \"0.1.{}\"\nedition = \"2021\"\n", i, i);
+            fs::write(input.join(format!("{:02}.toml", i)), text).unwrap();
+        }
+
+        let mut loose = Archive::create(&dir.join("loose.fca"), OutputPolicy::FailIfExists).unwrap();
+        loose.add(&input, Algorithm::Lzss).unwrap();
+        let mut solid = Archive::create(&dir.join("solid.fca"), OutputPolicy::FailIfExists).unwrap();
+        solid.add_solid(&[&input], Algorithm::Lzss, 300).unwrap();
+        assert!(fs::metadata(dir.join("solid.fca")).unwrap().len() < fs::metadata(dir.join("loose.fca")).unwrap().len());
+
+        let mut solid = Archive::open(&dir.join("solid.fca")).unwrap();
+        let mut blocks: Vec<u64> = solid.entries().iter().map(|entry| entry.offset).collect();
+        blocks.dedup();
+        assert!(blocks.len() > 1 && blocks.len() < 20);
+
+        let entry = solid.entries()[7].clone();
+        let mut output = Vec::new();
+        solid.read_entry(&entry, &mut output).unwrap();
+        assert_eq!(output, fs::read(input.join("07.toml")).unwrap());
+
+        solid.delete(&Pattern::new("**/0[2-8].toml").unwrap()).unwrap();
+        let out = dir.join("out");
+        let extracted = Archive::open(&dir.join("solid.fca")).unwrap().extract(&out, None, OutputPolicy::FailIfExists).unwrap();
+        assert_eq!(extracted.len(), 13);
+        for entry in extracted {
+            let name = entry.path.rsplit('/').next().unwrap();
+            assert_eq!(fs::read(out.join(&entry.path)).unwrap(), fs::read(input.join(name)).unwrap());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_add_leaves_archive_intact() {
+        let dir = scratch("failed_add");
+        let input = setup(&dir);
+        let archive_path = dir.join("test.fca");
+
+        let mut archive = Archive::create(&archive_path, OutputPolicy::FailIfExists).unwrap();
+        archive.add_file(&input.join("a.txt"), "a.txt", Algorithm::Lzss).unwrap();
+        let before = fs::read(&archive_path).unwrap();
+
+        // The first file fills a block, then the broken link fails the add
+        let solid = dir.join("solid");
+        fs::create_dir_all(&solid).unwrap();
+        fs::write(solid.join("1.txt"), b"one fish two fish red fish blue fish").unwrap();
+        std::os::unix::fs::symlink(dir.join("missing"), solid.join("2.txt")).unwrap();
+        assert!(archive.add_solid(&[&solid], Algorithm::Lzss, 1).is_err());
+        assert!(archive.add(&solid, Algorithm::Lzss).is_err());
+        assert_eq!(archive.entries().len(), 1);
+        assert_eq!(fs::read(&archive_path).unwrap(), before);
+
+        let mut archive = Archive::open(&archive_path).unwrap();
+        let entry = archive.entries()[0].clone();
+        let mut output = Vec::new();
+        archive.read_entry(&entry, &mut output).unwrap();
+        assert_eq!(output, fs::read(input.join("a.txt")).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_drops_special_bits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch("special_bits");
+        let input = setup(&dir);
+        fs::set_permissions(input.join("a.txt"), fs::Permissions::from_mode(0o4755)).unwrap();
+        let archive_path = dir.join("test.fca");
+
+        let mut archive = Archive::create(&archive_path, OutputPolicy::FailIfExists).unwrap();
+        archive.add_file(&input.join("a.txt"), "a.txt", Algorithm::Lzss).unwrap();
+        assert_eq!(archive.entries()[0].mode, 0o4755);
+
+        let out = dir.join("out");
+        archive.extract(&out, None, OutputPolicy::FailIfExists).unwrap();
+        assert_eq!(fs::metadata(out.join("a.txt")).unwrap().permissions().mode() & 0o7777, 0o755);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn archive_path_of(path: &Path) -> String {
+        archive_path(path).unwrap()
+    }
+}
@@ -0,0 +1,74 @@
+use std::io::{self, ErrorKind};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::error::{Error, Result};
+
+pub struct AsyncBitReader<R: AsyncRead + Unpin> {
+    inner: R,
+    byte: u8,
+    pos: u8,
+}
+
+impl<R: AsyncRead + Unpin> AsyncBitReader<R> {
+    /// Instantiates a new `AsyncBitReader`
+    pub fn new(inner: R) -> AsyncBitReader<R> {
+        AsyncBitReader {
+            inner,
+            byte: 0,
+            pos: 0
+        }
+    }
+
+    /// Reads in one bit of data.
+    /// 
+    /// Fails if EOF or can't read in data
+    pub async fn read_bit(&mut self) -> Result<u8> {
+        if self.pos == 0 {
+            let mut buf = [0u8; 1];
+            match self.inner.read(&mut buf).await? {
+                0 => return Err(Error::Io(io::Error::new(ErrorKind::UnexpectedEof, "EOF reached"))),
+                _ => self.byte = buf[0],
+            }
+        }
+
+        let bit = (self.byte >> (7 - self.pos)) & 1;
+        self.pos += 1;
+
+        if self.pos == 8 {
+            self.reset();
+        }
+
+        Ok(bit)
+    }
+
+    /// Reads in one byte of data.
+    /// 
+    /// Fails if EOF or can't to read in data
+    pub async fn read_byte(&mut self) -> Result<u8> {
+        let mut byte = 0u8;
+        for i in (0u8..=7).rev() {
+            let bit = self.read_bit().await?;
+            byte |= bit << i;
+        }
+
+        Ok(byte)
+    }
+
+    /// Reads in two bytes of data in big endian
+    ///
+    /// Fails if EOF or can't read in data
+    pub async fn read_u16(&mut self) -> Result<u16> {
+        let mut output = 0u16;
+        for i in (0u8..=15).rev() {
+            let bit = self.read_bit().await?;
+            output |= (bit as u16) << i;
+        }
+
+        Ok(output)
+    }
+
+    fn reset(&mut self) {
+        self.byte = 0;
+        self.pos = 0;
+    }
+}
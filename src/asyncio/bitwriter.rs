@@ -0,0 +1,76 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::error::Result;
+
+/// Async version of [`BitWriter`](crate::bitwriter::BitWriter).
+///
+/// There is no async `Drop`, so [`flush`](AsyncBitWriter::flush) must be
+/// awaited once writing is done or the last partial byte is lost.
+pub struct AsyncBitWriter<W: AsyncWrite + Unpin> {
+    inner: W,
+    byte: u8,
+    pos: u8
+}
+
+impl<W: AsyncWrite + Unpin> AsyncBitWriter<W> {
+    /// Instantiates a new `AsyncBitWriter`
+    pub fn new(inner: W) -> AsyncBitWriter<W> {
+        AsyncBitWriter { 
+            inner, 
+            byte: 0, 
+            pos: 0 
+        }
+    }
+
+    /// Adds one bit to the buffer and attempts to write 
+    /// once enough bits are formed to create a byte
+    pub async fn write_bit(&mut self, input: bool) -> Result<()> {
+        if input {
+            self.byte |= 1 << (7 - self.pos);
+        }
+        self.pos += 1;
+
+        if self.pos == 8 {
+            self.inner.write_all(&[self.byte]).await?;
+            self.reset();
+        }
+
+        Ok(())
+    }
+
+    /// Attempts to write a byte to the writer
+    pub async fn write_u8(&mut self, input: u8) -> Result<()> {
+        for i in (0u8..=7).rev() {
+            let bit = ((input >> i) & 1) == 1;
+            self.write_bit(bit).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Attempts to write two bytes to the writer
+    pub async fn write_u16(&mut self, input: u16) -> Result<()> {
+        for i in (0u8..=15).rev() {
+            let bit = ((input >> i) & 1) == 1;
+            self.write_bit(bit).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Attempts to flush the writer
+    pub async fn flush(&mut self) -> Result<()> {
+        if self.pos > 0 {
+            self.inner.write_all(&[self.byte]).await?;
+            self.reset();
+        } 
+        self.inner.flush().await?;
+
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.byte = 0;
+        self.pos = 0;
+    }
+}
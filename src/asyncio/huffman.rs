@@ -0,0 +1,85 @@
+use std::io::{Read, Write};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::codec::{Codec, Encoder};
+use crate::error::Result;
+use crate::huffman::Huffman;
+
+impl Huffman {
+    /// Async version of [`HuffmanEncoder`](crate::huffman::HuffmanEncoder),
+    /// writing the length and the tree in front of the codes so the output
+    /// can be read back with [`Huffman::read_input_async`]
+    ///
+    /// Buffers rather than streams: like the sync encoder, the whole input
+    /// is read into memory first
+    pub async fn write_output_async<R, W>(reader: &mut R, writer: &mut W) -> Result<()> 
+        where R: AsyncRead + Unpin, W: AsyncWrite + Unpin
+    {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+
+        let mut encoder = Huffman.encoder(Vec::new());
+        encoder.write_all(&data)?;
+        let output = encoder.finish()?;
+
+        writer.write_all(&output).await?;
+        writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// Async version of [`HuffmanDecoder`](crate::huffman::HuffmanDecoder)
+    ///
+    /// Buffers rather than streams: the whole input is read into memory
+    /// before it is decoded
+    pub async fn read_input_async<R, W>(reader: &mut R, writer: &mut W) -> Result<()> 
+        where R: AsyncRead + Unpin, W: AsyncWrite + Unpin
+    {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+
+        let mut decoder = Huffman.decoder(&data[..]);
+        let mut buffer = vec![0u8; 8192];
+        loop {
+            let len = decoder.read(&mut buffer)?;
+            if len == 0 { break; }
+            writer.write_all(&buffer[..len]).await?;
+        }
+        writer.flush().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_round_trip_matches_sync() {
+        let input = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("green.txt")).unwrap();
+        for data in [&input[..], b"", b"a"] {
+            let mut encoder = Huffman.encoder(Vec::new());
+            encoder.write_all(data).unwrap();
+            let sync_output = encoder.finish().unwrap();
+
+            let mut async_output = Vec::new();
+            Huffman::write_output_async(&mut &data[..], &mut async_output).await.unwrap();
+            assert_eq!(sync_output, async_output);
+
+            let mut decoded = Vec::new();
+            Huffman::read_input_async(&mut &async_output[..], &mut decoded).await.unwrap();
+            assert_eq!(decoded, data);
+        }
+
+        let mut compressed = Vec::new();
+        Huffman::write_output_async(&mut &input[..], &mut compressed).await.unwrap();
+        compressed.truncate(20);
+        let err = Huffman::read_input_async(&mut &compressed[..], &mut Vec::new()).await.unwrap_err();
+        assert!(matches!(err, crate::error::Error::Corrupt(_)), "{}", err);
+    }
+}
@@ -0,0 +1,169 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::asyncio::{AsyncBitReader, AsyncBitWriter};
+use crate::error::{truncated, Result};
+use crate::lzss::{token_flag, token_reference, Window, LZSS, Output, BUFFER_SIZE};
+
+impl LZSS {
+    /// Async version of [`LZSS::write_output`]
+    ///
+    /// Every chunk is filled completely before being encoded so the output
+    /// does not depend on how the reader splits up its reads
+    /// 
+    /// Returns [`Result<()>`] if it fails to read or write
+    pub async fn write_output_async<R, W>(
+        reader: &mut R,
+        writer: &mut W
+    ) -> Result<()> 
+        where R: AsyncRead + Unpin, W: AsyncWrite + Unpin
+    {
+        let mut buffer = Vec::with_capacity(BUFFER_SIZE);
+        let mut writer = AsyncBitWriter::new(writer);
+
+        loop {
+            // Reads until the buffer is full or the reader is empty, like `fill_buffer`
+            buffer.clear();
+            let len = (&mut *reader).take(BUFFER_SIZE as u64).read_to_end(&mut buffer).await?;
+            if len == 0 { break; }
+
+            let encoded_output = Self::encode(&buffer, len);
+            for val in encoded_output.into_iter() {
+                match val {
+                    Output::Literal(val) => {
+                        writer.write_bit(true).await?;
+                        writer.write_u8(val).await?;
+                    },
+                    Output::Reference { offset, length } => {
+                        writer.write_bit(false).await?;
+                        let double_byte = offset << 4 | length as u16;
+                        writer.write_u16(double_byte).await?;
+                    },
+                }
+            }
+        }
+        writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// Async version of [`LZSS::read_input`]
+    pub async fn read_input_async<R, W>(
+        reader: &mut R,
+        writer: &mut W
+    ) -> Result<()> 
+        where R: AsyncRead + Unpin, W: AsyncWrite + Unpin
+    {
+        let mut reader = AsyncBitReader::new(reader);
+        let mut window = Window::new();
+        let mut decoded = Vec::with_capacity(16);
+
+        // The same steps as the sync `Tokens`, only the reads are awaited
+        while let Some(flag) = token_flag(reader.read_bit().await)? {
+            if flag {
+                let literal = reader.read_byte().await.map_err(truncated)?;
+                window.literal(literal, &mut decoded);
+            } else {
+                let Some(reference) = token_reference(reader.read_u16().await)? else {
+                    break;
+                };
+                window.reference(reference, &mut decoded)?;
+            }
+            writer.write_all(&decoded).await?;
+            decoded.clear();
+        }
+        writer.flush().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read};
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use tokio::io::ReadBuf;
+
+    use super::*;
+
+    const INPUT: &[u8] = include_bytes!("../../green.txt");
+
+    // Hands out at most 64 bytes per read, like a pipe or socket
+    struct ShortReader<'a>(&'a [u8]);
+
+    impl Read for ShortReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(64);
+            Read::read(&mut self.0, &mut buf[..len])
+        }
+    }
+
+    impl AsyncRead for ShortReader<'_> {
+        fn poll_read(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            let len = buf.remaining().min(64).min(self.0.len());
+            buf.put_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_output_matches_sync() {
+        let mut sync_output = Vec::new();
+        LZSS::write_output(&mut &INPUT[..], &mut sync_output).unwrap();
+
+        let mut async_output = Vec::new();
+        LZSS::write_output_async(&mut &INPUT[..], &mut async_output).await.unwrap();
+
+        assert_eq!(sync_output, async_output);
+    }
+
+    #[tokio::test]
+    async fn test_short_reads_match_sync() {
+        let mut sync_output = Vec::new();
+        LZSS::write_output(&mut ShortReader(INPUT), &mut sync_output).unwrap();
+
+        let mut async_output = Vec::new();
+        LZSS::write_output_async(&mut ShortReader(INPUT), &mut async_output).await.unwrap();
+
+        let mut expected = Vec::new();
+        LZSS::write_output(&mut &INPUT[..], &mut expected).unwrap();
+        assert_eq!(sync_output, expected);
+        assert_eq!(async_output, expected);
+    }
+
+    #[tokio::test]
+    async fn test_read_input_matches_sync() {
+        let mut encoded = Vec::new();
+        LZSS::write_output(&mut &INPUT[..], &mut encoded).unwrap();
+
+        let mut sync_output = Vec::new();
+        LZSS::read_input(&mut &encoded[..], &mut sync_output).unwrap();
+
+        let mut async_output = Vec::new();
+        LZSS::read_input_async(&mut &encoded[..], &mut async_output).await.unwrap();
+
+        assert_eq!(sync_output, async_output);
+    }
+
+    #[tokio::test]
+    async fn test_read_input_stops_like_sync() {
+        // Stops at the end marker, leaving what follows in the reader
+        let mut encoded = Vec::new();
+        LZSS::write_stream(&mut &INPUT[..], &mut encoded, crate::lzss::MAX_LEVEL).unwrap();
+        encoded.extend_from_slice(b"after");
+
+        let mut reader = &encoded[..];
+        let mut async_output = Vec::new();
+        LZSS::read_input_async(&mut reader, &mut async_output).await.unwrap();
+        assert_eq!(async_output, INPUT);
+        assert_eq!(reader, b"after");
+
+        // Rejects the same bad reference
+        let bad = [0b0000_0000, 0b0001_0000, 0b1000_0000];
+        let sync_err = LZSS::read_input(&mut &bad[..], &mut Vec::new()).unwrap_err();
+        let async_err = LZSS::read_input_async(&mut &bad[..], &mut Vec::new()).await.unwrap_err();
+        assert_eq!(sync_err.to_string(), async_err.to_string());
+    }
+}
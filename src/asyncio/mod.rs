@@ -0,0 +1,13 @@
+//! Async counterparts of the bit I/O and codecs, built on tokio's
+//! `AsyncRead`/`AsyncWrite`. Enabled with the `async` feature.
+//!
+//! Everything here writes exactly the same bytes as the sync path.
+//! The LZSS functions stream, the Huffman ones buffer the whole input.
+
+mod bitreader;
+mod bitwriter;
+mod huffman;
+mod lzss;
+
+pub use bitreader::AsyncBitReader;
+pub use bitwriter::AsyncBitWriter;
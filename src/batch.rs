@@ -0,0 +1,223 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use file_compressor::{Error, Result};
+use glob::Pattern;
+
+/// Extensions of formats that are already compressed and gain nothing
+/// from another pass
+pub const COMPRESSED_EXTENSIONS: [&str; 25] = [
+    "fcmp", "fcar", "gz", "tgz", "zz", "bz2", "xz", "lz", "lz4", "lzma", "zst", "zip", "7z",
+    "rar", "jar", "jpg", "jpeg", "png", "gif", "webp", "mp3", "ogg", "mp4", "mkv", "pdf",
+];
+
+/// Decides which files found inside a directory are compressed
+///
+/// Patterns are matched against the path relative to the directory, so
+/// `*.txt` matches text files at any depth and `build/*` only below `build`
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    skip_compressed: bool,
+}
+
+impl Filter {
+    /// Instantiates a new `Filter`. With no include patterns every file is included
+    pub fn new(include: &[String], exclude: &[String], skip_compressed: bool) -> Result<Filter> {
+        Ok(Filter {
+            include: patterns(include)?,
+            exclude: patterns(exclude)?,
+            skip_compressed,
+        })
+    }
+
+    /// Returns true if a file at `relative` should be compressed
+    pub fn accepts(&self, relative: &Path) -> bool {
+        if self.skip_compressed && is_compressed(relative) {
+            return false;
+        }
+        if self.excludes(relative) {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(|pattern| pattern.matches_path(relative))
+    }
+
+    // Excluded directories are not walked at all
+    fn excludes(&self, relative: &Path) -> bool {
+        self.exclude.iter().any(|pattern| pattern.matches_path(relative))
+    }
+}
+
+fn patterns(globs: &[String]) -> Result<Vec<Pattern>> {
+    globs.iter()
+        .map(|glob| Pattern::new(glob)
+            .map_err(|e| Error::InvalidParams(format!("Invalid pattern {}: {}", glob, e))))
+        .collect()
+}
+
+/// Returns true if the file name has the extension of an already compressed format
+pub fn is_compressed(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| COMPRESSED_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(ext)))
+}
+
+/// Returns every regular file below `root` accepted by `filter`, in sorted order
+///
+/// Symbolic links are not followed, so a link back up the tree cannot loop
+pub fn walk(root: &Path, filter: &Filter) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut stack = vec![root.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        let mut children = fs::read_dir(&dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        // Sorted in reverse so they come off the stack in order
+        children.sort_by(|a, b| b.cmp(a));
+
+        for path in children {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let kind = fs::symlink_metadata(&path)?.file_type();
+            if kind.is_dir() {
+                if !filter.excludes(relative) {
+                    stack.push(path);
+                }
+            } else if kind.is_file() && filter.accepts(relative) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Returns where `file` found below `root` goes in a mirror tree at `output_dir`
+///
+/// The tree starts with the name of `root`, so several directories can share
+/// one output directory
+pub fn mirror(root: &Path, file: &Path, output_dir: &Path) -> PathBuf {
+    let relative = file.strip_prefix(root).unwrap_or(file);
+    match root.file_name() {
+        Some(name) => output_dir.join(name).join(relative),
+        None => output_dir.join(relative),
+    }
+}
+
+/// Runs `f` on every item with at most `workers` threads
+///
+/// Items are handed out one at a time, so a few large files do not hold
+/// up the rest. The results are in the same order as the items
+pub fn run<T, F>(items: &[T], workers: usize, f: F) -> Vec<Result<()>>
+where
+    T: Sync,
+    F: Fn(&T) -> Result<()> + Sync
+{
+    let workers = workers.clamp(1, items.len().max(1));
+    if workers == 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<Result<()>>>> = items.iter().map(|_| Mutex::new(None)).collect();
+
+    thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = f(item);
+                *results[index].lock().unwrap() = Some(result);
+            });
+        }
+    });
+
+    results.into_iter()
+        .map(|result| result.into_inner().unwrap().expect("every item is run"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("file_compressor_batch_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn tree(dir: &Path) {
+        for file in ["a.txt", "b.log", "photo.JPG", "sub/c.txt", "sub/d.gz", "target/e.txt"] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+        }
+    }
+
+    fn relative(dir: &Path, files: Vec<PathBuf>) -> Vec<String> {
+        files.iter()
+            .map(|file| file.strip_prefix(dir).unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_walk_skips_compressed() {
+        let dir = scratch("skip");
+        tree(&dir);
+
+        let filter = Filter::new(&[], &[], true).unwrap();
+        assert_eq!(relative(&dir, walk(&dir, &filter).unwrap()), ["a.txt", "b.log", "sub/c.txt", "target/e.txt"]);
+
+        let filter = Filter::new(&[], &[], false).unwrap();
+        assert_eq!(walk(&dir, &filter).unwrap().len(), 6);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_walk_include_exclude() {
+        let dir = scratch("globs");
+        tree(&dir);
+
+        let filter = Filter::new(&["*.txt".to_string()], &["target".to_string()], true).unwrap();
+        assert_eq!(relative(&dir, walk(&dir, &filter).unwrap()), ["a.txt", "sub/c.txt"]);
+
+        let filter = Filter::new(&[], &["sub/*".to_string(), "*.log".to_string()], true).unwrap();
+        assert_eq!(relative(&dir, walk(&dir, &filter).unwrap()), ["a.txt", "target/e.txt"]);
+
+        assert!(matches!(Filter::new(&["[".to_string()], &[], true), Err(Error::InvalidParams(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_mirror() {
+        let output = Path::new("out");
+        assert_eq!(mirror(Path::new("data"), Path::new("data/sub/a.txt"), output), Path::new("out/data/sub/a.txt"));
+        assert_eq!(mirror(Path::new("."), Path::new("./a.txt"), output), Path::new("out/a.txt"));
+    }
+
+    #[test]
+    fn test_run_keeps_order() {
+        let items: Vec<u32> = (0..100).collect();
+        let results = run(&items, 4, |item| match item % 7 {
+            0 => Err(Error::Io(io::Error::other(item.to_string()))),
+            _ => Ok(()),
+        });
+
+        assert_eq!(results.len(), items.len());
+        for (item, result) in items.iter().zip(results) {
+            assert_eq!(result.is_err(), item % 7 == 0);
+        }
+        assert!(run(&Vec::<u32>::new(), 8, |_| Ok(())).is_empty());
+    }
+}
@@ -1,19 +1,32 @@
-use std::io::{Read, Bytes, Result};
-use std::io::{Error, ErrorKind};
+//! Reads single bits from any `Read`, most significant bit first unless asked otherwise
+
+use std::io::{self, Read, Bytes, ErrorKind};
+
+use crate::bitwriter::BitOrder;
+use crate::error::{Error, Result};
 
 pub struct BitReader<R: Read> {
     inner: Bytes<R>,
     byte: u8,
     pos: u8,
+    order: BitOrder,
 }
 
 impl<R:Read> BitReader<R> {
     /// Instantiates a new `BitReader`
     pub fn new(inner: R) -> BitReader<R> {
+        Self::with_order(inner, BitOrder::MsbFirst)
+    }
+
+    /// Instantiates a new `BitReader` for bits packed in `order`
+    // Deliberately unbuffered so no bytes past the last bit read are consumed
+    #[allow(clippy::unbuffered_bytes)]
+    pub fn with_order(inner: R, order: BitOrder) -> BitReader<R> {
         BitReader {
             inner: inner.bytes(),
             byte: 0,
-            pos: 0
+            pos: 0,
+            order,
         }
     }
 
@@ -24,12 +37,15 @@ impl<R:Read> BitReader<R> {
         if self.pos == 0 {
             match self.inner.next() {
                 Some(Ok(byte)) => self.byte = byte,
-                Some(Err(e)) => return Err(e),
-                None => return Err(Error::new(ErrorKind::UnexpectedEof, "EOF reached")),
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err(Error::Io(io::Error::new(ErrorKind::UnexpectedEof, "EOF reached"))),
             }
         }
 
-        let bit = (self.byte >> (7 - self.pos)) & 1;
+        let bit = match self.order {
+            BitOrder::MsbFirst => (self.byte >> (7 - self.pos)) & 1,
+            BitOrder::LsbFirst => (self.byte >> self.pos) & 1,
+        };
         self.pos += 1;
 
         if self.pos == 8 {
@@ -40,16 +56,18 @@ impl<R:Read> BitReader<R> {
     }
 
     /// Reads in one byte of data.
-    /// 
+    ///
+    /// Its bits are taken in the same order as the bytes are packed, so a
+    /// byte read on a byte boundary comes out unchanged
+    ///
     /// Fails if EOF or can't to read in data
     pub fn read_byte(&mut self) -> Result<u8> {
-        let mut byte = 0u8;
-        for i in (0u8..=7).rev() {
-            let bit = self.read_bit()?;
-            byte |= bit << i;
-        }
+        let byte = match self.order {
+            BitOrder::MsbFirst => self.read_bits(8)?,
+            BitOrder::LsbFirst => self.read_bits_le(8)?,
+        };
 
-        Ok(byte)
+        Ok(byte as u8)
     }
 
     /// Reads in two bytes of data in big endian
@@ -65,6 +83,36 @@ impl<R:Read> BitReader<R> {
         Ok(output)
     }
 
+    /// Reads `count` bits, most significant first
+    ///
+    /// Fails if EOF or can't read in data
+    pub fn read_bits(&mut self, count: u8) -> Result<u32> {
+        let mut output = 0u32;
+        for _ in 0..count {
+            output = output << 1 | self.read_bit()? as u32;
+        }
+
+        Ok(output)
+    }
+
+    /// Reads `count` bits, least significant first as DEFLATE writes
+    /// everything but huffman codes
+    ///
+    /// Fails if EOF or can't read in data
+    pub fn read_bits_le(&mut self, count: u8) -> Result<u32> {
+        let mut output = 0u32;
+        for i in 0..count {
+            output |= (self.read_bit()? as u32) << i;
+        }
+
+        Ok(output)
+    }
+
+    /// Skips the rest of a partly read byte
+    pub fn align(&mut self) {
+        self.reset();
+    }
+
     fn reset(&mut self) {
         self.byte = 0;
         self.pos = 0;
@@ -1,18 +1,39 @@
-use std::io::{Write, BufWriter, Error, Result};
+//! Writes single bits to any `Write`, most significant bit first unless asked otherwise
+
+use std::io::Write;
+
+use crate::error::Result;
+
+/// The order bits are packed into each byte
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BitOrder {
+    /// Most significant bit first, as in the crate's own formats
+    #[default]
+    MsbFirst,
+    /// Least significant bit first, as in DEFLATE
+    LsbFirst,
+}
 
 pub struct BitWriter<W: Write> {
     inner: W,
     byte: u8,
-    pos: u8
+    pos: u8,
+    order: BitOrder,
 }
 
 impl<W: Write> BitWriter<W> {
     /// Instantiates a new `BitWriter`
     pub fn new(inner: W) -> BitWriter<W> {
-        BitWriter { 
-            inner, 
-            byte: 0, 
-            pos: 0 
+        Self::with_order(inner, BitOrder::MsbFirst)
+    }
+
+    /// Instantiates a new `BitWriter` that packs bits in `order`
+    pub fn with_order(inner: W, order: BitOrder) -> BitWriter<W> {
+        BitWriter {
+            inner,
+            byte: 0,
+            pos: 0,
+            order,
         }
     }
 
@@ -20,7 +41,10 @@ impl<W: Write> BitWriter<W> {
     /// one enough bits are formed to create a byte
     pub fn write_bit(&mut self, input: bool) -> Result<()> {
         if input {
-            self.byte |= 1 << (7 - self.pos);
+            self.byte |= match self.order {
+                BitOrder::MsbFirst => 1 << (7 - self.pos),
+                BitOrder::LsbFirst => 1 << self.pos,
+            };
         }
         self.pos += 1;
 
@@ -33,13 +57,14 @@ impl<W: Write> BitWriter<W> {
     }
 
     /// Attempts to write a byte to the writer
+    ///
+    /// Its bits go in the same order as the bytes are packed, so a byte
+    /// written on a byte boundary comes out unchanged
     pub fn write_u8(&mut self, input: u8) -> Result<()> {
-        for i in (0u8..=7).rev() {
-            let bit = ((input >> i) & 1) == 1;
-            self.write_bit(bit)?;
+        match self.order {
+            BitOrder::MsbFirst => self.write_bits(input as u32, 8),
+            BitOrder::LsbFirst => self.write_bits_le(input as u32, 8),
         }
-
-        Ok(())
     }
 
     /// Attempts to write two bytes to the writer
@@ -52,25 +77,59 @@ impl<W: Write> BitWriter<W> {
         Ok(())
     }
 
-    /// Attempts to flush the writer
-    pub fn flush(&mut self) -> Result<()> {
+    /// Writes the lowest `count` bits of `input`, most significant first
+    pub fn write_bits(&mut self, input: u32, count: u8) -> Result<()> {
+        for i in (0..count).rev() {
+            self.write_bit((input >> i) & 1 == 1)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the lowest `count` bits of `input`, least significant first
+    /// as DEFLATE writes everything but huffman codes
+    pub fn write_bits_le(&mut self, input: u32, count: u8) -> Result<()> {
+        for i in 0..count {
+            self.write_bit((input >> i) & 1 == 1)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pads a partial byte with zero bits and writes it, without flushing
+    /// the underlying writer
+    pub fn align(&mut self) -> Result<()> {
         if self.pos > 0 {
             self.inner.write_all(&[self.byte])?;
             self.reset();
-        } 
+        }
+
+        Ok(())
+    }
+
+    /// Attempts to flush the writer
+    pub fn flush(&mut self) -> Result<()> {
+        self.align()?;
         self.inner.flush()?;
 
         Ok(())
     }
 
+    /// Returns a mutable reference to the underlying writer
+    ///
+    /// Bits of a partial byte are still held by the `BitWriter`
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Flushes any partial byte and returns the underlying writer
+    pub fn into_inner(mut self) -> Result<W> {
+        self.flush()?;
+        Ok(self.inner)
+    }
+
     fn reset(&mut self) {
         self.byte = 0;
         self.pos = 0;
     }
-}
-
-impl<W: Write> Drop for BitWriter<W> {
-    fn drop(&mut self) {
-        self.flush();
-    }
 }
\ No newline at end of file
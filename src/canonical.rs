@@ -0,0 +1,274 @@
+//! Canonical huffman codes for alphabets of any size, with a limit on code length
+
+use std::cmp::Reverse;
+use std::io::{Read, Write};
+
+use crate::bitreader::BitReader;
+use crate::bitwriter::BitWriter;
+use crate::error::{truncated, Error, Result};
+use crate::heap::Heap;
+
+/// Longest code a [`CanonicalCode`] can have
+pub const MAX_CODE_LENGTH: u8 = 15;
+
+/// A prefix code described only by the length of each symbol's code
+///
+/// Codes of the same length are consecutive and in symbol order, so the
+/// lengths are all a decoder needs to rebuild them
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CanonicalCode {
+    lengths: Vec<u8>,
+    codes: Vec<u16>,
+    // Number of codes of each length, and the symbols in code order
+    counts: [u16; MAX_CODE_LENGTH as usize + 1],
+    symbols: Vec<u16>,
+}
+
+impl CanonicalCode {
+    /// Builds the code for symbol frequencies with no code longer than `max_length`
+    ///
+    /// Symbols with a frequency of zero get no code
+    pub fn from_freqs(freqs: &[usize], max_length: u8) -> CanonicalCode {
+        CanonicalCode::build(code_lengths(freqs, max_length))
+    }
+
+    /// Rebuilds a code from the length of every symbol's code, zero for none
+    ///
+    /// Fails if a length is too long or there are more codes of some
+    /// length than fit. Codes that leave gaps are allowed
+    pub fn from_lengths(lengths: &[u8]) -> Result<CanonicalCode> {
+        if lengths.iter().any(|&len| len > MAX_CODE_LENGTH) {
+            return Err(Error::Corrupt("Data is corrupt: huffman code is too long".to_string()));
+        }
+
+        let mut left = 1i32;
+        for len in 1..=MAX_CODE_LENGTH {
+            left = (left << 1) - lengths.iter().filter(|&&l| l == len).count() as i32;
+            if left < 0 {
+                return Err(Error::Corrupt("Data is corrupt: too many huffman codes".to_string()));
+            }
+        }
+        Ok(CanonicalCode::build(lengths.to_vec()))
+    }
+
+    fn build(lengths: Vec<u8>) -> CanonicalCode {
+        let mut counts = [0u16; MAX_CODE_LENGTH as usize + 1];
+        for &len in lengths.iter().filter(|&&len| len > 0) {
+            counts[len as usize] += 1;
+        }
+
+        let mut next = [0u32; MAX_CODE_LENGTH as usize + 1];
+        let mut code = 0u32;
+        for len in 1..next.len() {
+            code = (code + counts[len - 1] as u32) << 1;
+            next[len] = code;
+        }
+
+        let codes = lengths.iter()
+            .map(|&len| match len {
+                0 => 0,
+                len => {
+                    let code = next[len as usize];
+                    next[len as usize] += 1;
+                    code as u16
+                },
+            })
+            .collect();
+
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16).filter(|&sym| lengths[sym as usize] > 0).collect();
+        symbols.sort_by_key(|&sym| lengths[sym as usize]);
+
+        CanonicalCode { lengths, codes, counts, symbols }
+    }
+
+    /// Returns the code length of every symbol, zero for none
+    pub fn lengths(&self) -> &[u8] {
+        &self.lengths
+    }
+
+    /// Returns the code of a symbol and its length
+    pub fn code(&self, symbol: usize) -> (u16, u8) {
+        (self.codes[symbol], self.lengths[symbol])
+    }
+
+    /// Returns true if no symbol has a code
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Writes the code of a symbol, first bit of the code first
+    pub fn write_symbol<W: Write>(&self, symbol: usize, writer: &mut BitWriter<W>) -> Result<()> {
+        match self.code(symbol) {
+            (_, 0) => Err(Error::InvalidParams(format!("Symbol {} has no huffman code", symbol))),
+            (code, len) => writer.write_bits(code as u32, len),
+        }
+    }
+
+    /// Reads bits until they make up a code and returns its symbol
+    ///
+    /// Fails if EOF or the bits are not the code of any symbol
+    pub fn read_symbol<R: Read>(&self, reader: &mut BitReader<R>) -> Result<usize> {
+        if self.is_empty() {
+            return Err(Error::Corrupt("Data is corrupt: symbol from an empty huffman code".to_string()));
+        }
+
+        // The first code of each length and the index of its symbol
+        let (mut code, mut first, mut index) = (0u32, 0u32, 0usize);
+        for &count in &self.counts[1..] {
+            code |= reader.read_bit().map_err(truncated)? as u32;
+            let count = count as u32;
+            if code < first + count {
+                return Ok(self.symbols[index + (code - first) as usize] as usize);
+            }
+            index += count as usize;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(Error::Corrupt("Data is corrupt: not a huffman code".to_string()))
+    }
+}
+
+/// Returns the huffman code length of every symbol, none longer than `max_length`
+///
+/// A single symbol gets a length of one. When the tree is too deep the
+/// least frequent of the longest codes that can still grow are lengthened
+/// until the code fits, then the most frequent of the longest codes are
+/// shortened until no code is left unused, since zlib rejects codes with gaps
+pub fn code_lengths(freqs: &[usize], max_length: u8) -> Vec<u8> {
+    let mut lengths = vec![0u8; freqs.len()];
+    let used: Vec<usize> = (0..freqs.len()).filter(|&sym| freqs[sym] > 0).collect();
+    match used[..] {
+        [] => return lengths,
+        [sym] => {
+            lengths[sym] = 1;
+            return lengths;
+        },
+        _ => (),
+    }
+
+    // Leaves are the used symbols, internal nodes follow in the order they are made
+    let mut parents = vec![0usize; used.len()];
+    let mut heap = Heap::new_min(used.iter().enumerate().map(|(node, &sym)| (freqs[sym], node)).collect());
+    while let Some((freq, left)) = heap.remove() {
+        let Some((right_freq, right)) = heap.remove() else {
+            break;
+        };
+        let node = parents.len();
+        parents.push(0);
+        parents[left] = node;
+        parents[right] = node;
+        heap.insert((freq + right_freq, node));
+    }
+
+    // Parents come after their children, so depths fill in from the root down
+    let mut depths = vec![0u8; parents.len()];
+    for node in (0..parents.len() - 1).rev() {
+        depths[node] = depths[parents[node]].saturating_add(1);
+    }
+    for (node, &sym) in used.iter().enumerate() {
+        lengths[sym] = depths[node].min(max_length);
+    }
+
+    // Sum of 2^-length scaled by 2^max_length, a complete code sums to exactly one
+    let kraft = |lengths: &[u8]| -> u64 {
+        used.iter().map(|&sym| 1u64 << (max_length - lengths[sym])).sum()
+    };
+    let mut total = kraft(&lengths);
+    while total > 1 << max_length {
+        let sym = used.iter()
+            .copied()
+            .filter(|&sym| lengths[sym] < max_length)
+            .max_by_key(|&sym| (lengths[sym], Reverse(freqs[sym])))
+            .expect("an alphabet no larger than 2^max_length fits");
+        total -= 1 << (max_length - lengths[sym] - 1);
+        lengths[sym] += 1;
+    }
+    // The gap is a multiple of what shortening a longest code takes up
+    while total < 1 << max_length {
+        let sym = used.iter()
+            .copied()
+            .max_by_key(|&sym| (lengths[sym], freqs[sym]))
+            .expect("there are at least two symbols");
+        total += 1 << (max_length - lengths[sym]);
+        lengths[sym] -= 1;
+    }
+    lengths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc_1951_example() {
+        // The example of section 3.2.2: ABCDEFGH with lengths (3, 3, 3, 3, 3, 2, 4, 4)
+        let code = CanonicalCode::from_lengths(&[3, 3, 3, 3, 3, 2, 4, 4]).unwrap();
+        let codes: Vec<_> = (0..8).map(|sym| code.code(sym)).collect();
+        assert_eq!(codes, [(0b010, 3), (0b011, 3), (0b100, 3), (0b101, 3), (0b110, 3), (0b00, 2), (0b1110, 4), (0b1111, 4)]);
+    }
+
+    #[test]
+    fn test_lengths_are_limited() {
+        // Fibonacci frequencies make the deepest possible tree
+        let mut freqs = vec![1usize, 1];
+        while freqs.len() < 30 {
+            freqs.push(freqs[freqs.len() - 1] + freqs[freqs.len() - 2]);
+        }
+        assert_eq!(*code_lengths(&freqs, 32).iter().max().unwrap(), 29);
+
+        for max in [7, 10, MAX_CODE_LENGTH] {
+            let lengths = code_lengths(&freqs, max);
+            assert!(lengths.iter().all(|&len| len > 0 && len <= max));
+            let kraft: f64 = lengths.iter().map(|&len| 0.5f64.powi(len as i32)).sum();
+            assert_eq!(kraft, 1.0, "{:?}", lengths);
+            // The most frequent symbol still gets the shortest code
+            assert_eq!(lengths.iter().min(), lengths.last());
+        }
+    }
+
+    #[test]
+    fn test_small_alphabets() {
+        assert_eq!(code_lengths(&[0, 0, 0], 15), [0, 0, 0]);
+        assert_eq!(code_lengths(&[0, 5, 0], 15), [0, 1, 0]);
+        assert_eq!(code_lengths(&[3, 0, 4], 15), [1, 0, 1]);
+        assert!(CanonicalCode::from_freqs(&[0, 0], 15).is_empty());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = b"it was the best of times, it was the worst of times";
+        let mut freqs = vec![0usize; 300];
+        for &byte in text {
+            freqs[byte as usize] += 1;
+        }
+        freqs[290] = 1;
+        let code = CanonicalCode::from_freqs(&freqs, MAX_CODE_LENGTH);
+
+        let mut encoded = Vec::new();
+        let mut writer = BitWriter::new(&mut encoded);
+        for &byte in text {
+            code.write_symbol(byte as usize, &mut writer).unwrap();
+        }
+        code.write_symbol(290, &mut writer).unwrap();
+        assert!(code.write_symbol(b'z' as usize, &mut writer).is_err());
+        writer.flush().unwrap();
+
+        let rebuilt = CanonicalCode::from_lengths(code.lengths()).unwrap();
+        let mut reader = BitReader::new(&encoded[..]);
+        for &byte in text {
+            assert_eq!(rebuilt.read_symbol(&mut reader).unwrap(), byte as usize);
+        }
+        assert_eq!(rebuilt.read_symbol(&mut reader).unwrap(), 290);
+    }
+
+    #[test]
+    fn test_rejects_bad_lengths() {
+        assert!(matches!(CanonicalCode::from_lengths(&[1, 1, 1]), Err(Error::Corrupt(_))));
+        assert!(matches!(CanonicalCode::from_lengths(&[16]), Err(Error::Corrupt(_))));
+
+        // A gap in the code is allowed but reading into it fails
+        let code = CanonicalCode::from_lengths(&[1, 0, 2]).unwrap();
+        assert_eq!(code.read_symbol(&mut BitReader::new(&[0b1000_0000u8][..])).unwrap(), 2);
+        assert!(matches!(code.read_symbol(&mut BitReader::new(&[0b1100_0000u8][..])), Err(Error::Corrupt(_))));
+    }
+}
@@ -0,0 +1,270 @@
+//! CRC32 checksums of data as it is read or written, and the Adler-32 of zlib streams
+
+use std::fmt::{self, Display};
+use std::io::{self, Read, Write};
+
+use crc32fast::Hasher;
+
+/// Decoded data did not match the checksum stored with it
+///
+/// Returned as [`Error::ChecksumMismatch`](crate::error::Error::ChecksumMismatch)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChecksumError {
+    /// The first block that failed, or `None` for the whole content
+    pub block: Option<u64>,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.block {
+            Some(block) => write!(f, "Data is corrupt: checksum mismatch in block {}", block)?,
+            None => write!(f, "Data is corrupt: checksum mismatch")?,
+        }
+        write!(f, " (expected {:08x}, got {:08x})", self.expected, self.actual)
+    }
+}
+
+impl std::error::Error for ChecksumError {}
+
+/// Returns the CRC32 of every `block_size` bytes of `data`
+pub fn block_checksums(data: &[u8], block_size: usize) -> Vec<u32> {
+    data.chunks(block_size).map(crc32fast::hash).collect()
+}
+
+/// A writer that passes everything through while keeping a CRC32 of
+/// the whole content and, optionally, of every fixed size block
+pub struct ChecksumWriter<W: Write> {
+    inner: W,
+    content: Hasher,
+    block: Hasher,
+    block_size: Option<u64>,
+    block_fill: u64,
+    blocks: Vec<u32>,
+    written: u64,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    /// Instantiates a new `ChecksumWriter`
+    pub fn new(inner: W, block_size: Option<u32>) -> ChecksumWriter<W> {
+        ChecksumWriter {
+            inner,
+            content: Hasher::new(),
+            block: Hasher::new(),
+            block_size: block_size.map(u64::from),
+            block_fill: 0,
+            blocks: Vec::new(),
+            written: 0,
+        }
+    }
+
+    /// Returns the number of bytes written so far
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Returns the content checksum and the checksum of every block,
+    /// including a last partial block
+    pub fn finish(mut self) -> (u32, Vec<u32>) {
+        if self.block_fill > 0 {
+            self.blocks.push(self.block.clone().finalize());
+        }
+        (self.content.finalize(), self.blocks)
+    }
+
+    fn update(&mut self, mut buf: &[u8]) {
+        self.content.update(buf);
+        self.written += buf.len() as u64;
+
+        let block_size = match self.block_size {
+            Some(block_size) => block_size,
+            None => return,
+        };
+
+        while !buf.is_empty() {
+            let take = buf.len().min((block_size - self.block_fill) as usize);
+            self.block.update(&buf[..take]);
+            self.block_fill += take as u64;
+            buf = &buf[take..];
+
+            if self.block_fill == block_size {
+                let block = std::mem::replace(&mut self.block, Hasher::new());
+                self.blocks.push(block.finalize());
+                self.block_fill = 0;
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A reader that passes everything through while keeping a CRC32 of it
+/// and, optionally, of every fixed size block
+pub struct ChecksumReader<R: Read> {
+    inner: R,
+    sums: ChecksumWriter<io::Sink>,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    /// Instantiates a new `ChecksumReader`
+    pub fn new(inner: R) -> ChecksumReader<R> {
+        Self::with_blocks(inner, None)
+    }
+
+    /// Instantiates a new `ChecksumReader` that also keeps a CRC32 of
+    /// every `block_size` bytes
+    pub fn with_blocks(inner: R, block_size: Option<u32>) -> ChecksumReader<R> {
+        ChecksumReader {
+            inner,
+            sums: ChecksumWriter::new(io::sink(), block_size),
+        }
+    }
+
+    /// Returns the number of bytes read so far
+    pub fn read_bytes(&self) -> u64 {
+        self.sums.written()
+    }
+
+    /// Returns the checksum of everything read so far
+    pub fn checksum(&self) -> u32 {
+        self.sums.content.clone().finalize()
+    }
+
+    /// Returns the checksum of everything read
+    pub fn finish(self) -> u32 {
+        self.finish_blocks().0
+    }
+
+    /// Returns the content checksum and the checksum of every block,
+    /// including a last partial block
+    pub fn finish_blocks(self) -> (u32, Vec<u32>) {
+        self.sums.finish()
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.sums.update(&buf[..len]);
+        Ok(len)
+    }
+}
+
+// Largest prime below 2^16, and how many bytes can be summed before the sums could overflow a u32
+const ADLER_MODULUS: u32 = 65521;
+const ADLER_CHUNK: usize = 5552;
+
+/// The Adler-32 checksum of RFC 1950, which zlib streams end with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    /// Instantiates the checksum of no data
+    pub fn new() -> Adler32 {
+        Adler32 { a: 1, b: 0 }
+    }
+
+    /// Adds data to the checksum
+    pub fn update(&mut self, data: &[u8]) {
+        for chunk in data.chunks(ADLER_CHUNK) {
+            for &byte in chunk {
+                self.a += byte as u32;
+                self.b += self.a;
+            }
+            self.a %= ADLER_MODULUS;
+            self.b %= ADLER_MODULUS;
+        }
+    }
+
+    /// Returns the checksum of everything added
+    pub fn finish(&self) -> u32 {
+        self.b << 16 | self.a
+    }
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum_writer() {
+        let data: Vec<u8> = (0..2500u32).map(|i| (i * 7) as u8).collect();
+
+        let mut writer = ChecksumWriter::new(Vec::new(), Some(1000));
+        for chunk in data.chunks(333) {
+            writer.write_all(chunk).unwrap();
+        }
+        assert_eq!(writer.written(), 2500);
+
+        let (content, blocks) = writer.finish();
+        assert_eq!(content, crc32fast::hash(&data));
+        assert_eq!(blocks, block_checksums(&data, 1000));
+        assert_eq!(blocks.len(), 3);
+    }
+
+    #[test]
+    fn test_checksum_reader() {
+        let data: Vec<u8> = (0..2500u32).map(|i| (i * 3) as u8).collect();
+
+        let mut reader = ChecksumReader::new(&data[..]);
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+
+        assert_eq!(output, data);
+        assert_eq!(reader.read_bytes(), 2500);
+        assert_eq!(reader.finish(), crc32fast::hash(&data));
+
+        let mut reader = ChecksumReader::with_blocks(&data[..], Some(1000));
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(reader.finish_blocks(), (crc32fast::hash(&data), block_checksums(&data, 1000)));
+    }
+
+    #[test]
+    fn test_adler32() {
+        let mut adler = Adler32::new();
+        assert_eq!(adler.finish(), 1);
+        adler.update(b"Wikipedia");
+        assert_eq!(adler.finish(), 0x11e60398);
+
+        // Long enough to need the sums reduced along the way, fed in uneven pieces
+        let data: Vec<u8> = (0..100).flat_map(|_| 0..=255u8).collect();
+        let mut adler = Adler32::new();
+        for chunk in data.chunks(7000) {
+            adler.update(chunk);
+        }
+        assert_eq!(adler.finish(), 0x747fd0e0);
+    }
+
+    #[test]
+    fn test_error_round_trip() {
+        use crate::error::Error;
+
+        let err: io::Error = Error::from(ChecksumError { block: Some(4), expected: 1, actual: 2 }).into();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("block 4"));
+        match Error::from(err) {
+            Error::ChecksumMismatch(err) => assert_eq!(err.block, Some(4)),
+            err => panic!("came back as {:?}", err),
+        }
+    }
+}
@@ -0,0 +1,722 @@
+use std::fs::{self, File};
+use std::io::{self, stdout, BufRead, BufReader, BufWriter, ErrorKind, IsTerminal, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use glob::Pattern;
+
+use file_compressor::archive::{self, Archive};
+use crate::batch::{self, Filter};
+use file_compressor::container::{self, Algorithm, Options};
+use file_compressor::export;
+use file_compressor::fileops::OutputPolicy;
+use file_compressor::format::{self, Format};
+use file_compressor::gzip::{self, GzipHeader};
+use file_compressor::huffman::Huffman;
+use file_compressor::lzss;
+use file_compressor::metadata::{Metadata, Restore};
+use file_compressor::pipeline::Pipeline;
+use file_compressor::tree::HuffmanTree;
+use file_compressor::zlib;
+use file_compressor::{Error, Result};
+
+/// Everything worked
+pub const EXIT_OK: u8 = 0;
+/// Something could not be read or written, or an output already exists
+pub const EXIT_ERROR: u8 = 1;
+/// The arguments were wrong, this is also what clap exits with
+pub const EXIT_USAGE: u8 = 2;
+/// An input is not a compressed file, is corrupt or uses an unsupported format
+pub const EXIT_CORRUPT: u8 = 3;
+
+/// Compresses files with LZSS or Huffman coding, or into gzip and zlib files
+///
+/// Without a command it works like gzip: files are compressed, or
+/// decompressed with -d, and with no files it reads stdin and writes
+/// stdout. Run without any arguments from a terminal to start the
+/// terminal interface
+#[derive(Parser, Debug)]
+#[command(name = "file_compressor", version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    compress: CompressArgs,
+    /// Decompress instead of compress
+    #[arg(short, long)]
+    decompress: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compress files to FILE.fcmp, or FILE.gz or FILE.zz, removing the originals
+    Compress(CompressArgs),
+    /// Decompress files in any format, removing the compressed ones
+    Decompress(DecompressArgs),
+    /// Check that files decompress and match their checksums without writing anything
+    Test {
+        /// Files to check, `-` or nothing for stdin
+        files: Vec<PathBuf>,
+    },
+    /// Show what is in the header of compressed files
+    List {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Measure the ratio and speed of each algorithm on files
+    Bench {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Only measure this algorithm
+        #[arg(short, long, value_enum)]
+        algorithm: Option<AlgorithmArg>,
+        #[arg(short, long, default_value_t = lzss::MAX_LEVEL, value_parser = level_parser())]
+        level: u8,
+    },
+    /// Print the Huffman tree of a file as DOT or its code table as JSON
+    Tree {
+        file: PathBuf,
+        #[arg(value_enum, default_value_t = TreeFormat::Dot)]
+        format: TreeFormat,
+    },
+    /// Work with multi-file archives
+    #[command(subcommand)]
+    Archive(ArchiveCommand),
+}
+
+#[derive(Args, Debug)]
+struct CompressArgs {
+    /// Files or directories to compress, `-` or nothing for stdin
+    files: Vec<PathBuf>,
+    #[arg(short, long, value_enum, default_value_t = AlgorithmArg::Lzss)]
+    algorithm: AlgorithmArg,
+    /// Run these stages in order instead of one algorithm, from delta,
+    /// rle, lzss and huffman, e.g. `delta,lzss,huffman`
+    #[arg(short, long, value_name = "STAGES", conflicts_with = "algorithm")]
+    pipeline: Option<Pipeline>,
+    /// Write gzip or zlib, which other tools can read, instead of .fcmp
+    #[arg(short = 'F', long, value_enum, default_value_t = FormatArg::Fcmp, conflicts_with_all = ["algorithm", "pipeline"])]
+    format: FormatArg,
+    /// 1 is fastest, 9 compresses best
+    #[arg(short, long, default_value_t = lzss::MAX_LEVEL, value_parser = level_parser())]
+    level: u8,
+    /// Write to this file instead of FILE.fcmp, only with a single input
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Write to stdout and keep the input files
+    #[arg(short = 'c', long)]
+    stdout: bool,
+    /// Keep the input files
+    #[arg(short, long)]
+    keep: bool,
+    /// Overwrite existing outputs, write to a terminal and compress files
+    /// that already end in the format's suffix
+    #[arg(short, long)]
+    force: bool,
+    /// Write outputs into a mirror of the input tree below this directory
+    /// instead of beside the inputs
+    #[arg(long, value_name = "DIR", conflicts_with = "output")]
+    output_dir: Option<PathBuf>,
+    /// Only compress files inside directories that match this glob, may be repeated
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// Leave out files and directories that match this glob, may be repeated
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Also compress files inside directories that look already compressed, like .gz or .jpg
+    #[arg(long)]
+    all: bool,
+    /// Number of files compressed at once
+    #[arg(short, long, default_value_t = num_cpus::get(), value_parser = jobs_parser())]
+    jobs: usize,
+}
+
+#[derive(Args, Debug)]
+struct DecompressArgs {
+    /// Files to decompress, `-` or nothing for stdin
+    files: Vec<PathBuf>,
+    /// Write to this file, only with a single input
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Write to stdout and keep the input files
+    #[arg(short = 'c', long)]
+    stdout: bool,
+    /// Keep the compressed files
+    #[arg(short, long)]
+    keep: bool,
+    /// Overwrite existing outputs
+    #[arg(short, long)]
+    force: bool,
+    /// Name the output after the input instead of the stored name
+    #[arg(long)]
+    no_name: bool,
+    /// Do not restore the modification time
+    #[arg(long)]
+    no_mtime: bool,
+    /// Do not restore the permissions
+    #[arg(long)]
+    no_mode: bool,
+    /// Do not restore extended attributes
+    #[arg(long)]
+    no_xattrs: bool,
+    /// Also restore setuid, setgid and sticky bits and extended attributes
+    /// outside `user.`, only for files from a trusted source
+    #[arg(long)]
+    privileged: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum ArchiveCommand {
+    /// Add files or directories, creating the archive if needed
+    Add {
+        archive: PathBuf,
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        #[arg(short, long, value_enum, default_value_t = AlgorithmArg::Lzss)]
+        algorithm: AlgorithmArg,
+        /// Compress the files together so matches can cross file boundaries
+        #[arg(long)]
+        solid: bool,
+        /// Start a new solid block every this many MB, implies --solid
+        #[arg(long, value_name = "MB")]
+        solid_block: Option<u64>,
+    },
+    /// List the entries of an archive
+    List {
+        archive: PathBuf,
+    },
+    /// Extract entries below a directory
+    Extract {
+        archive: PathBuf,
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+        /// Only extract entries matching this glob
+        #[arg(long, value_name = "GLOB")]
+        only: Option<String>,
+        /// Overwrite existing files
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Delete entries matching a glob
+    Delete {
+        archive: PathBuf,
+        pattern: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum AlgorithmArg {
+    Lzss,
+    Huffman,
+    /// LZSS with huffman coded tokens, like DEFLATE
+    Lzh,
+}
+
+impl From<AlgorithmArg> for Algorithm {
+    fn from(arg: AlgorithmArg) -> Algorithm {
+        match arg {
+            AlgorithmArg::Lzss => Algorithm::Lzss,
+            AlgorithmArg::Huffman => Algorithm::Huffman,
+            AlgorithmArg::Lzh => Algorithm::Lzh,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum FormatArg {
+    /// This tool's own format, which can hold any algorithm
+    Fcmp,
+    /// DEFLATE in a .gz file, like gzip
+    Gzip,
+    /// DEFLATE in a .zz zlib stream
+    Zlib,
+}
+
+impl From<FormatArg> for Format {
+    fn from(arg: FormatArg) -> Format {
+        match arg {
+            FormatArg::Fcmp => Format::Fcmp,
+            FormatArg::Gzip => Format::Gzip,
+            FormatArg::Zlib => Format::Zlib,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum TreeFormat {
+    Dot,
+    Json,
+}
+
+fn level_parser() -> clap::builder::RangedI64ValueParser<u8> {
+    clap::value_parser!(u8).range(lzss::MIN_LEVEL as i64..=lzss::MAX_LEVEL as i64)
+}
+
+fn jobs_parser() -> clap::builder::RangedU64ValueParser<usize> {
+    clap::builder::RangedU64ValueParser::new().range(1..)
+}
+
+/// Runs a command and returns the exit code
+///
+/// Commands on several files carry on past a failing file and exit with
+/// the most serious code of any of them
+pub fn run(cli: Cli) -> ExitCode {
+    let command = match cli.command {
+        Some(command) => command,
+        None if cli.decompress => Command::Decompress(DecompressArgs::from(cli.compress)),
+        None => Command::Compress(cli.compress),
+    };
+
+    let code = match command {
+        Command::Compress(args) => compress(&args),
+        Command::Decompress(args) => decompress(&args),
+        Command::Test { files } => for_each(&or_stdin(files), |file| {
+            let decoded = format::decompress(&mut open(file)?, &mut io::sink())?;
+            println!("{}: OK ({}, {} bytes)", file.display(), decoded.codec, decoded.size);
+            Ok(())
+        }),
+        Command::List { files } => {
+            println!("{:>12} {:>12} {:>7}  {:<9} {:>12}  name", "compressed", "original", "saved", "algorithm", "mtime");
+            for_each(&files, list)
+        },
+        Command::Bench { files, algorithm, level } => for_each(&files, |file| bench(file, algorithm, level)),
+        Command::Tree { file, format } => report(&file, export_tree(&file, format)),
+        Command::Archive(command) => run_archive(command),
+    };
+    ExitCode::from(code)
+}
+
+/// Returns the exit code for an error
+pub fn exit_code(err: &Error) -> u8 {
+    match err {
+        Error::Corrupt(_) | Error::ChecksumMismatch(_) | Error::Unsupported(_) => EXIT_CORRUPT,
+        Error::InvalidParams(_) => EXIT_USAGE,
+        Error::Io(_) if err.is_eof() => EXIT_CORRUPT,
+        Error::Io(_) => EXIT_ERROR,
+    }
+}
+
+// Prints an error against the path it happened on and returns the exit code
+fn report(path: &Path, result: Result<()>) -> u8 {
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(err) => {
+            eprintln!("file_compressor: {}: {}", path.display(), err);
+            exit_code(&err)
+        },
+    }
+}
+
+fn for_each<F>(files: &[PathBuf], mut f: F) -> u8
+where
+    F: FnMut(&Path) -> Result<()>
+{
+    files.iter()
+        .map(|file| report(file, f(file)))
+        .max()
+        .unwrap_or(EXIT_OK)
+}
+
+fn usage(msg: &str) -> u8 {
+    eprintln!("file_compressor: {}", msg);
+    EXIT_USAGE
+}
+
+fn policy(force: bool) -> OutputPolicy {
+    match force {
+        true => OutputPolicy::Overwrite,
+        false => OutputPolicy::FailIfExists,
+    }
+}
+
+fn compress(args: &CompressArgs) -> u8 {
+    if args.output.is_some() && args.files.len() > 1 {
+        return usage("-o can only be used with a single file");
+    }
+    let options = match &args.pipeline {
+        Some(pipeline) => Options::new(Algorithm::Pipeline).pipeline(pipeline.clone()),
+        None => Options::new(args.algorithm.into()),
+    };
+    let options = options.level(args.level);
+    let suffix = Format::from(args.format).suffix();
+    let filter = match Filter::new(&args.include, &args.exclude, !args.all) {
+        Ok(filter) => filter,
+        Err(err) => return usage(&err.to_string()),
+    };
+    let to_stdout = args.stdout || args.output.as_deref().is_some_and(is_stdio);
+
+    // Pairs of input and output, found up front so they can be shared out
+    let mut jobs = Vec::new();
+    let mut code = EXIT_OK;
+    for file in or_stdin(args.files.clone()) {
+        if file.is_dir() {
+            if to_stdout || args.output.is_some() {
+                code = code.max(usage(&format!("{}: directories need an output per file, use --output-dir", file.display())));
+                continue;
+            }
+            match batch::walk(&file, &filter) {
+                Ok(found) => jobs.extend(found.into_iter().map(|input| {
+                    let output = match &args.output_dir {
+                        Some(dir) => with_suffix(&batch::mirror(&file, &input, dir), suffix),
+                        None => with_suffix(&input, suffix),
+                    };
+                    (input, output)
+                })),
+                Err(err) => code = code.max(report(&file, Err(err))),
+            }
+        } else if is_stdio(&file) || to_stdout {
+            code = code.max(report(&file, compress_to_stdout(&file, args.format.into(), &options, args.force)));
+        } else {
+            let output = match (&args.output, &args.output_dir) {
+                (Some(output), _) => output.clone(),
+                (None, Some(dir)) => dir.join(with_suffix(Path::new(file.file_name().unwrap_or_default()), suffix)),
+                (None, None) => with_suffix(&file, suffix),
+            };
+            jobs.push((file, output));
+        }
+    }
+
+    let results = batch::run(&jobs, args.jobs, |(input, output)| compress_file(input, output, &options, args));
+    jobs.iter()
+        .zip(results)
+        .map(|((input, _), result)| report(input, result))
+        .fold(code, u8::max)
+}
+
+fn compress_file(file: &Path, output: &Path, options: &Options, args: &CompressArgs) -> Result<()> {
+    let format = Format::from(args.format);
+    if !args.force && file.extension().is_some_and(|ext| ext == format.suffix()) {
+        return Err(Error::Io(io::Error::new(ErrorKind::AlreadyExists, format!("already has .{} suffix", format.suffix()))));
+    }
+    if !file.is_file() {
+        return Err(Error::InvalidParams("not a regular file".to_string()));
+    }
+
+    if let Some(parent) = output.parent().filter(|_| args.output_dir.is_some()) {
+        fs::create_dir_all(parent)?;
+    }
+    format::compress_file(file, output, format, options, policy(args.force))?;
+
+    if !args.keep {
+        fs::remove_file(file)?;
+    }
+    Ok(())
+}
+
+// Compresses a file or stdin to stdout. Input that cannot be rewound is
+// streamed, which only LZSS can do in a container
+fn compress_to_stdout(file: &Path, format: Format, options: &Options, force: bool) -> Result<()> {
+    let stdout = io::stdout();
+    if !force && stdout.is_terminal() {
+        return Err(Error::Io(io::Error::other("refusing to write compressed data to a terminal, use -f to force")));
+    }
+    let mut writer = BufWriter::new(stdout.lock());
+
+    if format != Format::Fcmp {
+        let header = match is_stdio(file) {
+            true => GzipHeader::default(),
+            false => GzipHeader::from(&Metadata::from_path(file)?),
+        };
+        let mut reader = open(file)?;
+        match format {
+            Format::Gzip => gzip::compress(&mut reader, &mut writer, &header, options.level)?,
+            _ => zlib::compress(&mut reader, &mut writer, options.level)?,
+        }
+    } else if !is_stdio(file) {
+        let options = options.clone().metadata(Metadata::from_path(file)?);
+        container::compress_with(&mut BufReader::new(File::open(file)?), &mut writer, &options)?;
+    } else if let Some(mut stdin) = seekable_stdin() {
+        container::compress_with(&mut BufReader::new(&mut stdin), &mut writer, options)?;
+    } else {
+        if options.algorithm != Algorithm::Lzss {
+            let wanted = options.pipeline.as_ref().map_or_else(|| format!("{:?}", options.algorithm), Pipeline::to_string);
+            eprintln!("file_compressor: stdin cannot be rewound, using {:?} instead of {}", Algorithm::Lzss, wanted);
+        }
+        let options = Options { algorithm: Algorithm::Lzss, pipeline: None, ..options.clone() };
+        container::compress_stream(&mut io::stdin().lock(), &mut writer, &options)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn decompress(args: &DecompressArgs) -> u8 {
+    if args.output.is_some() && args.files.len() > 1 {
+        return usage("-o can only be used with a single file");
+    }
+    let restore = Restore {
+        name: !args.no_name,
+        mtime: !args.no_mtime,
+        mode: !args.no_mode,
+        xattrs: !args.no_xattrs,
+        privileged: args.privileged,
+    };
+
+    for_each(&or_stdin(args.files.clone()), |file| {
+        if is_stdio(file) || args.stdout || args.output.as_deref().is_some_and(is_stdio) {
+            let mut writer = BufWriter::new(io::stdout().lock());
+            format::decompress(&mut open(file)?, &mut writer)?;
+            writer.flush()?;
+            return Ok(());
+        }
+
+        format::decompress_file(file, args.output.as_deref(), &restore, policy(args.force))?;
+        if !args.keep {
+            fs::remove_file(file)?;
+        }
+        Ok(())
+    })
+}
+
+impl From<CompressArgs> for DecompressArgs {
+    fn from(args: CompressArgs) -> DecompressArgs {
+        DecompressArgs {
+            files: args.files,
+            output: args.output,
+            stdout: args.stdout,
+            keep: args.keep,
+            force: args.force,
+            no_name: false,
+            no_mtime: false,
+            no_mode: false,
+            no_xattrs: false,
+            privileged: false,
+        }
+    }
+}
+
+// `-` stands for stdin or stdout
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+// No files means stdin
+fn or_stdin(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    match files.is_empty() {
+        true => vec![PathBuf::from("-")],
+        false => files,
+    }
+}
+
+// Opens a file, or stdin for `-`
+fn open(file: &Path) -> Result<Box<dyn BufRead>> {
+    match is_stdio(file) {
+        true => Ok(Box::new(io::stdin().lock())),
+        false => Ok(Box::new(BufReader::new(File::open(file)?))),
+    }
+}
+
+// Returns stdin as a file if it can be rewound, like a redirected file
+#[cfg(unix)]
+fn seekable_stdin() -> Option<File> {
+    use std::os::fd::AsFd;
+
+    let mut file = File::from(io::stdin().as_fd().try_clone_to_owned().ok()?);
+    file.stream_position().ok().map(|_| file)
+}
+
+#[cfg(not(unix))]
+fn seekable_stdin() -> Option<File> {
+    None
+}
+
+// Prints one line of `list` for a compressed file
+fn list(file: &Path) -> Result<()> {
+    let compressed = fs::metadata(file)?.len();
+    let summary = format::describe(file)?;
+
+    let saved = match summary.size {
+        0 => 0.0,
+        original => 100.0 * (1.0 - compressed as f64 / original as f64),
+    };
+    let mtime = summary.mtime.map_or_else(|| "-".to_string(), |mtime| mtime.to_string());
+    let name = summary.name
+        .unwrap_or_else(|| file.with_extension("").display().to_string());
+
+    println!("{:>12} {:>12} {:>6.1}%  {:<9} {:>12}  {}",
+        compressed, summary.size, saved, summary.codec, mtime, name);
+    Ok(())
+}
+
+// Compresses and decompresses a file in memory with every algorithm and prints the results
+fn bench(file: &Path, algorithm: Option<AlgorithmArg>, level: u8) -> Result<()> {
+    let data = fs::read(file)?;
+    let algorithms = match algorithm {
+        Some(algorithm) => vec![algorithm.into()],
+        None => vec![Algorithm::Lzss, Algorithm::Huffman, Algorithm::Lzh],
+    };
+
+    for algorithm in algorithms {
+        let options = Options::new(algorithm).level(level);
+
+        let start = Instant::now();
+        let mut compressed = Vec::new();
+        container::compress_with(&mut &data[..], &mut compressed, &options)?;
+        let compress_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut decompressed = Vec::with_capacity(data.len());
+        container::decompress(&mut &compressed[..], &mut decompressed)?;
+        let decompress_time = start.elapsed();
+
+        if decompressed != data {
+            return Err(Error::Corrupt(format!("{:?} did not round trip", algorithm)));
+        }
+
+        let mb = data.len() as f64 / (1 << 20) as f64;
+        println!("{}: {:?} {} -> {} bytes ({:.1}%), compress {:.2} MB/s, decompress {:.2} MB/s",
+            file.display(),
+            algorithm,
+            data.len(),
+            compressed.len(),
+            100.0 * compressed.len() as f64 / data.len().max(1) as f64,
+            mb / compress_time.as_secs_f64(),
+            mb / decompress_time.as_secs_f64(),
+        );
+    }
+    Ok(())
+}
+
+// Prints the huffman tree of a file as DOT or its code table as JSON
+fn export_tree(path: &Path, format: TreeFormat) -> Result<()> {
+    let counts = Huffman::create_freq_table(&File::open(path)?)?;
+    let tree = HuffmanTree::from_counts(&counts);
+
+    let output = match format {
+        TreeFormat::Dot => export::tree_to_dot(&tree),
+        TreeFormat::Json => export::codes_to_json(&tree.codes())? + "\n",
+    };
+
+    stdout().write_all(output.as_bytes())?;
+    Ok(())
+}
+
+fn run_archive(command: ArchiveCommand) -> u8 {
+    let archive = match &command {
+        ArchiveCommand::Add { archive, .. }
+        | ArchiveCommand::List { archive }
+        | ArchiveCommand::Extract { archive, .. }
+        | ArchiveCommand::Delete { archive, .. } => archive.clone(),
+    };
+    report(&archive, archive_command(command))
+}
+
+fn archive_command(command: ArchiveCommand) -> Result<()> {
+    match command {
+        ArchiveCommand::Add { archive, paths, algorithm, solid, solid_block } => {
+            let mut handle = match archive.exists() {
+                true => Archive::open(&archive)?,
+                false => Archive::create(&archive, OutputPolicy::FailIfExists)?,
+            };
+
+            let block_size = match (solid, solid_block) {
+                (_, Some(mb)) => Some(mb << 20),
+                (true, None) => Some(archive::DEFAULT_SOLID_BLOCK_SIZE),
+                (false, None) => None,
+            };
+            let added = match block_size {
+                Some(block_size) => {
+                    let paths: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+                    handle.add_solid(&paths, algorithm.into(), block_size)?
+                },
+                None => paths.iter()
+                    .map(|path| handle.add(path, algorithm.into()))
+                    .collect::<Result<Vec<_>>>()?
+                    .concat(),
+            };
+            for entry in added {
+                println!("added {}", entry.path);
+            }
+        },
+        ArchiveCommand::List { archive } => {
+            for entry in Archive::open(&archive)?.entries() {
+                println!("{:o} {:>12} {:>12} {:?} {}",
+                    entry.mode, entry.size, entry.compressed_size(), entry.algorithm, entry.path);
+            }
+        },
+        ArchiveCommand::Extract { archive, dir, only, force } => {
+            let only = only.as_deref().map(pattern).transpose()?;
+            let mut handle = Archive::open(&archive)?;
+            for entry in handle.extract(&dir, only.as_ref(), policy(force))? {
+                println!("extracted {}", entry.path);
+            }
+        },
+        ArchiveCommand::Delete { archive, pattern: glob } => {
+            let mut handle = Archive::open(&archive)?;
+            for entry in handle.delete(&pattern(&glob)?)? {
+                println!("deleted {}", entry.path);
+            }
+        },
+    }
+    Ok(())
+}
+
+fn pattern(glob: &str) -> Result<Pattern> {
+    Pattern::new(glob)
+        .map_err(|e| Error::InvalidParams(format!("Invalid pattern {}: {}", glob, e)))
+}
+
+// Returns `file` with a compressed suffix added to its full name
+fn with_suffix(file: &Path, suffix: &str) -> PathBuf {
+    let mut name = file.as_os_str().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let cli = Cli::try_parse_from(["file_compressor", "compress", "-a", "huffman", "-l", "3", "-k", "a.txt"]).unwrap();
+        match cli.command {
+            Some(Command::Compress(args)) => {
+                assert_eq!(args.algorithm, AlgorithmArg::Huffman);
+                assert_eq!(args.level, 3);
+                assert!(args.keep && !args.force);
+                assert_eq!(args.files, vec![PathBuf::from("a.txt")]);
+            },
+            command => panic!("parsed as {:?}", command),
+        }
+
+        assert!(Cli::try_parse_from(["file_compressor", "compress", "-l", "10", "a.txt"]).is_err());
+        assert!(Cli::try_parse_from(["file_compressor", "decompress", "-l", "3"]).is_err());
+        assert!(Cli::try_parse_from(["file_compressor", "compress", "-j", "0", "a.txt"]).is_err());
+        assert!(Cli::try_parse_from(["file_compressor", "compress", "-o", "x", "--output-dir", "y", "a.txt"]).is_err());
+
+        let cli = Cli::try_parse_from(["file_compressor", "--pipeline", "delta,lzss,huffman", "a.txt"]).unwrap();
+        assert_eq!(cli.compress.pipeline.unwrap().to_string(), "delta,lzss,huffman");
+        assert!(Cli::try_parse_from(["file_compressor", "--pipeline", "lzss,zip", "a.txt"]).is_err());
+        assert!(Cli::try_parse_from(["file_compressor", "-a", "huffman", "-p", "lzss", "a.txt"]).is_err());
+
+        let cli = Cli::try_parse_from(["file_compressor", "--format", "gzip", "-l", "1", "a.txt"]).unwrap();
+        assert_eq!(cli.compress.format, FormatArg::Gzip);
+        assert_eq!(Format::from(cli.compress.format).suffix(), "gz");
+        assert!(Cli::try_parse_from(["file_compressor", "-F", "zlib", "-a", "lzh", "a.txt"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_gzip_style() {
+        let cli = Cli::try_parse_from(["file_compressor", "-dc", "x.fcmp"]).unwrap();
+        assert!(cli.command.is_none() && cli.decompress && cli.compress.stdout);
+        assert_eq!(cli.compress.files, vec![PathBuf::from("x.fcmp")]);
+
+        let cli = Cli::try_parse_from(["file_compressor", "-c", "-"]).unwrap();
+        assert!(cli.command.is_none() && !cli.decompress);
+        assert_eq!(or_stdin(cli.compress.files), vec![PathBuf::from("-")]);
+        assert_eq!(or_stdin(Vec::new()), vec![PathBuf::from("-")]);
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(exit_code(&Error::Corrupt("corrupt".to_string())), EXIT_CORRUPT);
+        assert_eq!(exit_code(&Error::InvalidParams("bad level".to_string())), EXIT_USAGE);
+        assert_eq!(exit_code(&Error::Io(io::Error::new(ErrorKind::NotFound, "missing"))), EXIT_ERROR);
+        assert_eq!(exit_code(&Error::Io(io::ErrorKind::UnexpectedEof.into())), EXIT_CORRUPT);
+        assert_eq!(with_suffix(Path::new("dir/a.txt"), "fcmp"), Path::new("dir/a.txt.fcmp"));
+    }
+}
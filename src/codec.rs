@@ -0,0 +1,125 @@
+//! A common interface to every codec, so one can be swapped for another
+
+use std::io::{Read, Write};
+
+use crate::error::Result;
+
+/// Turns a codec into streaming adapters, like flate2's `GzEncoder` and `GzDecoder`
+///
+/// ```
+/// use std::io::{self, Read};
+/// use file_compressor::codec::{Codec, Encoder};
+/// use file_compressor::lzss::LZSS;
+///
+/// let mut encoder = LZSS.encoder(Vec::new());
+/// io::copy(&mut &b"banana bandana"[..], &mut encoder).unwrap();
+/// let compressed = encoder.finish().unwrap();
+///
+/// let mut output = Vec::new();
+/// LZSS.decoder(&compressed[..]).read_to_end(&mut output).unwrap();
+/// assert_eq!(output, b"banana bandana");
+/// ```
+pub trait Codec {
+    type Encoder<W: Write>: Encoder<W>;
+    type Decoder<R: Read>: Read;
+
+    /// Returns a writer that compresses everything written to it into `writer`
+    fn encoder<W: Write>(&self, writer: W) -> Self::Encoder<W>;
+
+    /// Returns a reader that decompresses what the encoder wrote from `reader`
+    ///
+    /// Nothing past the end of the compressed data is read
+    fn decoder<R: Read>(&self, reader: R) -> Self::Decoder<R>;
+}
+
+/// A writer that compresses into another writer
+///
+/// The end of the data is only written by [`Encoder::finish`], an encoder
+/// that is dropped without it leaves its output cut short
+pub trait Encoder<W: Write>: Write {
+    /// Writes out everything still held and returns the underlying writer
+    fn finish(self) -> Result<W>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::Path;
+
+    use super::*;
+    use crate::deflate::Deflate;
+    use crate::huffman::Huffman;
+    use crate::lzh::Lzh;
+    use crate::lzss::LZSS;
+
+    fn sample() -> Vec<u8> {
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lzss.rs")).unwrap()
+    }
+
+    fn round_trip<C: Codec>(codec: &C, data: &[u8]) -> Vec<u8> {
+        let mut encoder = codec.encoder(Vec::new());
+        io::copy(&mut &data[..], &mut encoder).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut output = Vec::new();
+        io::copy(&mut codec.decoder(&compressed[..]), &mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = sample();
+        for data in [&data[..], b"", b"a"] {
+            assert_eq!(round_trip(&LZSS, data), data);
+            assert_eq!(round_trip(&Huffman, data), data);
+            assert_eq!(round_trip(&Lzh, data), data);
+            assert_eq!(round_trip(&Deflate, data), data);
+        }
+    }
+
+    #[test]
+    fn test_small_writes_and_reads() {
+        let data = sample();
+        let mut encoder = Huffman.encoder(Vec::new());
+        for chunk in data.chunks(7) {
+            encoder.write_all(chunk).unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+
+        let mut decoder = Huffman.decoder(&compressed[..]);
+        let mut output = Vec::new();
+        let mut chunk = [0u8; 5];
+        loop {
+            match decoder.read(&mut chunk).unwrap() {
+                0 => break,
+                len => output.extend_from_slice(&chunk[..len]),
+            }
+        }
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn test_lzss_matches_write_stream() {
+        let data = sample();
+        let mut expected = Vec::new();
+        LZSS::write_stream(&mut &data[..], &mut expected, crate::lzss::MAX_LEVEL).unwrap();
+
+        let mut encoder = LZSS.encoder(Vec::new());
+        encoder.write_all(&data).unwrap();
+        assert_eq!(encoder.finish().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_decoder_stops_at_end() {
+        let mut compressed = LZSS.encoder(Vec::new());
+        compressed.write_all(b"first").unwrap();
+        let mut compressed = compressed.finish().unwrap();
+        compressed.extend_from_slice(b"rest");
+
+        let mut reader = &compressed[..];
+        let mut output = Vec::new();
+        LZSS.decoder(&mut reader).read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"first");
+        assert_eq!(reader, b"rest");
+    }
+}
@@ -0,0 +1,923 @@
+//! The `.fcmp` format: a header naming the algorithm and size, the compressed data and a checksum trailer
+
+use std::fs::File;
+use std::io::{Read, Write, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::checksum::{self, ChecksumError, ChecksumReader, ChecksumWriter};
+use crate::error::{truncated, Error, Result};
+use crate::huffman::Huffman;
+use crate::lzh::Lzh;
+use crate::lzss::{self, LZSS};
+use crate::fileops::{self, OutputPolicy};
+use crate::metadata::{Metadata, Restore};
+use crate::pipeline::Pipeline;
+
+/// Bytes every compressed file starts with
+pub const MAGIC: [u8; 4] = *b"FCMP";
+
+/// Newest format version this build can read and the one it writes
+pub const VERSION: u8 = 1;
+
+/// The trailer holds a CRC32 of the uncompressed content
+pub const FLAG_CHECKSUM: u8 = 0b0000_0001;
+
+/// The trailer also holds a CRC32 of every block of the uncompressed content
+pub const FLAG_BLOCK_CHECKSUMS: u8 = 0b0000_0010;
+
+/// The header holds the original file's [`Metadata`]
+pub const FLAG_METADATA: u8 = 0b0000_0100;
+
+/// The data was compressed as it was read, so the original size is not
+/// in the header. The LZSS data ends with an end marker and the original
+/// size goes in the trailer instead
+pub const FLAG_STREAMED: u8 = 0b0000_1000;
+
+/// Block size used for per-block checksums unless one is given
+pub const DEFAULT_BLOCK_SIZE: u32 = 1 << 20;
+
+// Flags this version knows about. Anything else is rejected
+const KNOWN_FLAGS: u8 = FLAG_CHECKSUM | FLAG_BLOCK_CHECKSUMS | FLAG_METADATA | FLAG_STREAMED;
+
+// Window and look-ahead sizes the LZSS codec is built with
+const LZSS_WINDOW: u16 = 4095;
+const LZSS_LOOK_AHEAD: u8 = 15;
+
+/// The codec used for the data after the header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Lzss,
+    Huffman,
+    /// LZSS tokens written with huffman codes
+    Lzh,
+    /// A [`Pipeline`] of stages, whose ids are the header parameters
+    Pipeline,
+}
+
+impl Algorithm {
+    /// Returns the id written to the header
+    pub fn id(self) -> u8 {
+        match self {
+            Algorithm::Lzss => 1,
+            Algorithm::Huffman => 2,
+            Algorithm::Pipeline => 3,
+            Algorithm::Lzh => 4,
+        }
+    }
+
+    /// Returns the algorithm for a header id
+    pub fn from_id(id: u8) -> Option<Algorithm> {
+        match id {
+            1 => Some(Algorithm::Lzss),
+            2 => Some(Algorithm::Huffman),
+            3 => Some(Algorithm::Pipeline),
+            4 => Some(Algorithm::Lzh),
+            _ => None,
+        }
+    }
+
+    /// Returns the codec parameters written to the header. A pipeline's
+    /// come from its stages instead
+    pub fn params(self) -> Vec<u8> {
+        match self {
+            Algorithm::Lzss | Algorithm::Lzh => {
+                let [hi, lo] = LZSS_WINDOW.to_be_bytes();
+                vec![hi, lo, LZSS_LOOK_AHEAD]
+            },
+            Algorithm::Huffman | Algorithm::Pipeline => Vec::new(),
+        }
+    }
+}
+
+/// The header at the start of every compressed file
+///
+/// | field         | size     |
+/// |---------------|----------|
+/// | magic         | 4        |
+/// | version       | 1        |
+/// | algorithm     | 1        |
+/// | flags         | 1        |
+/// | params length | 1        |
+/// | params        | variable |
+/// | original size | 8 (BE), not with [`FLAG_STREAMED`] |
+/// | block size    | 4 (BE), only with [`FLAG_BLOCK_CHECKSUMS`] |
+/// | metadata      | variable, only with [`FLAG_METADATA`] |
+///
+/// The compressed data follows, with its length (8, BE) in front for a
+/// [`Algorithm::Pipeline`], then the trailer: the original size
+/// (8, BE) with [`FLAG_STREAMED`], one CRC32 per block with
+/// [`FLAG_BLOCK_CHECKSUMS`] and the content CRC32 with [`FLAG_CHECKSUM`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub version: u8,
+    pub algorithm: Algorithm,
+    pub flags: u8,
+    pub params: Vec<u8>,
+    pub original_size: u64,
+    pub block_size: Option<u32>,
+    pub metadata: Option<Metadata>,
+}
+
+impl Header {
+    /// Instantiates a header for the current version with a content checksum
+    pub fn new(algorithm: Algorithm, original_size: u64) -> Header {
+        Header {
+            version: VERSION,
+            algorithm,
+            flags: FLAG_CHECKSUM,
+            params: algorithm.params(),
+            original_size,
+            block_size: None,
+            metadata: None,
+        }
+    }
+
+    /// Adds a checksum for every `block_size` bytes of content
+    pub fn with_block_checksums(mut self, block_size: u32) -> Header {
+        self.flags |= FLAG_BLOCK_CHECKSUMS;
+        self.block_size = Some(block_size);
+        self
+    }
+
+    /// Switches the algorithm to a pipeline of these stages
+    pub fn with_pipeline(mut self, pipeline: &Pipeline) -> Header {
+        self.algorithm = Algorithm::Pipeline;
+        self.params = pipeline.params();
+        self
+    }
+
+    /// Returns the pipeline for [`Algorithm::Pipeline`]
+    pub fn pipeline(&self) -> Option<Pipeline> {
+        match self.algorithm {
+            Algorithm::Pipeline => Pipeline::from_params(&self.params).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the algorithm's name, or the stages of a pipeline
+    pub fn codec(&self) -> String {
+        match self.pipeline() {
+            Some(pipeline) => pipeline.to_string(),
+            None => format!("{:?}", self.algorithm),
+        }
+    }
+
+    /// Records the metadata of the original file
+    pub fn with_metadata(mut self, metadata: Metadata) -> Header {
+        self.flags |= FLAG_METADATA;
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Returns true if the original size is in the trailer instead of the header
+    pub fn is_streamed(&self) -> bool {
+        self.flags & FLAG_STREAMED != 0
+    }
+
+    /// Returns the number of blocks that have their own checksum
+    pub fn block_count(&self) -> u64 {
+        match self.block_size {
+            Some(block_size) => self.original_size.div_ceil(block_size as u64),
+            None => 0,
+        }
+    }
+
+    /// Writes the header to a writer
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[self.version, self.algorithm.id(), self.flags, self.params.len() as u8])?;
+        writer.write_all(&self.params)?;
+        if !self.is_streamed() {
+            writer.write_all(&self.original_size.to_be_bytes())?;
+        }
+        if let Some(block_size) = self.block_size {
+            writer.write_all(&block_size.to_be_bytes())?;
+        }
+        if let Some(metadata) = &self.metadata {
+            metadata.write_to(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a header and checks that this version can decode what follows
+    ///
+    /// Fails if the magic bytes are wrong, or the version, algorithm,
+    /// flags or parameters are not supported
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Header> {
+        let magic: [u8; 4] = read_array(reader)?;
+        if magic != MAGIC {
+            return Err(invalid("Not a compressed file (bad magic bytes)"));
+        }
+
+        let [version, algorithm, flags, params_len] = read_array(reader)?;
+
+        if version == 0 || version > VERSION {
+            return Err(unsupported(format!(
+                "Unsupported format version {} (newest supported is {})", version, VERSION
+            )));
+        }
+
+        let algorithm = Algorithm::from_id(algorithm)
+            .ok_or_else(|| unsupported(format!("Unknown algorithm id {}", algorithm)))?;
+
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(unsupported(format!("Unknown flags {:#010b}", flags & !KNOWN_FLAGS)));
+        }
+
+        let mut params = vec![0u8; params_len as usize];
+        reader.read_exact(&mut params).map_err(|e| truncated(e.into()))?;
+        if algorithm == Algorithm::Pipeline {
+            Pipeline::from_params(&params)?;
+        } else if params != algorithm.params() {
+            return Err(unsupported(format!("Unsupported {:?} parameters {:?}", algorithm, params)));
+        }
+
+        let streamed = flags & FLAG_STREAMED != 0;
+        if streamed && (algorithm != Algorithm::Lzss || flags & FLAG_BLOCK_CHECKSUMS != 0) {
+            return Err(unsupported(format!("Streamed {:?} data with flags {:#010b}", algorithm, flags)));
+        }
+
+        let size = match streamed {
+            true => [0u8; 8],
+            false => read_array(reader)?,
+        };
+
+        let block_size = match flags & FLAG_BLOCK_CHECKSUMS {
+            0 => None,
+            _ => match read_u32(reader)? {
+                0 => return Err(invalid("Block size of zero")),
+                block_size => Some(block_size),
+            },
+        };
+
+        let metadata = match flags & FLAG_METADATA {
+            0 => None,
+            _ => Some(Metadata::read_from(reader)?),
+        };
+
+        Ok(Header {
+            version,
+            algorithm,
+            flags,
+            params,
+            original_size: u64::from_be_bytes(size),
+            block_size,
+            metadata,
+        })
+    }
+}
+
+/// How data is written into a container
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub algorithm: Algorithm,
+    pub block_size: Option<u32>,
+    pub metadata: Option<Metadata>,
+    /// Compression level from [`lzss::MIN_LEVEL`] to [`lzss::MAX_LEVEL`],
+    /// used by everything built on LZSS
+    pub level: u8,
+    /// The stages for [`Algorithm::Pipeline`]
+    pub pipeline: Option<Pipeline>,
+}
+
+impl Options {
+    /// Instantiates options with only a content checksum
+    pub fn new(algorithm: Algorithm) -> Options {
+        Options {
+            algorithm,
+            block_size: None,
+            metadata: None,
+            level: lzss::MAX_LEVEL,
+            pipeline: None,
+        }
+    }
+
+    /// Encodes with a pipeline of stages instead of a single algorithm
+    pub fn pipeline(mut self, pipeline: Pipeline) -> Options {
+        self.algorithm = Algorithm::Pipeline;
+        self.pipeline = Some(pipeline);
+        self
+    }
+
+    /// Sets the compression level
+    pub fn level(mut self, level: u8) -> Options {
+        self.level = level;
+        self
+    }
+
+    /// Adds a checksum for every `block_size` bytes of content
+    pub fn block_checksums(mut self, block_size: u32) -> Options {
+        self.block_size = Some(block_size);
+        self
+    }
+
+    /// Records the metadata of the original file in the header
+    pub fn metadata(mut self, metadata: Metadata) -> Options {
+        self.metadata = Some(metadata);
+        self
+    }
+}
+
+/// Compresses everything in the reader into a container
+pub fn compress<R: Read, W: Write>(reader: &mut R, writer: &mut W, algorithm: Algorithm) -> Result<Header> {
+    compress_with(reader, writer, &Options::new(algorithm))
+}
+
+/// Compresses everything in the reader into a container with the given options
+///
+/// The original size goes in the header, so the input is read into memory
+/// first. Use [`compress_sized`] when the size is known up front
+pub fn compress_with<R: Read, W: Write>(reader: &mut R, writer: &mut W, options: &Options) -> Result<Header> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    compress_data(&data, writer, options)
+}
+
+/// Compresses a reader that holds exactly `size` bytes into a container
+///
+/// LZSS and LZH are encoded as the input is read. Huffman and pipelines
+/// need all of it at once, so for them the input is still read into
+/// memory. Fails if the reader ends early or holds more, as a file can
+/// change while it is read
+pub fn compress_sized<R: Read, W: Write>(reader: &mut R, writer: &mut W, size: u64, options: &Options) -> Result<Header> {
+    if !matches!(options.algorithm, Algorithm::Lzss | Algorithm::Lzh) {
+        let mut data = Vec::new();
+        reader.take(size).read_to_end(&mut data)?;
+        check_size(reader, data.len() as u64, size)?;
+        return compress_data(&data, writer, options);
+    }
+
+    let (header, _) = header_for(options, size)?;
+    header.write_to(writer)?;
+
+    let mut input = ChecksumReader::with_blocks(reader.take(size), header.block_size);
+    match options.algorithm {
+        Algorithm::Lzss => LZSS::write_output_level(&mut input, writer, options.level)?,
+        _ => Lzh::write_output_level(&mut input, writer, options.level)?,
+    }
+    let read = input.read_bytes();
+    let (content, blocks) = input.finish_blocks();
+    check_size(reader, read, size)?;
+
+    write_trailer(writer, &header, content, &blocks)?;
+    Ok(header)
+}
+
+// Compresses data that is already in memory
+fn compress_data<W: Write>(data: &[u8], writer: &mut W, options: &Options) -> Result<Header> {
+    let (header, pipeline) = header_for(options, data.len() as u64)?;
+    header.write_to(writer)?;
+
+    match options.algorithm {
+        Algorithm::Lzss => LZSS::write_output_level(&mut &data[..], writer, options.level)?,
+        Algorithm::Huffman => Huffman::encode_data(data, writer)?,
+        Algorithm::Lzh => Lzh::write_output_level(&mut &data[..], writer, options.level)?,
+        Algorithm::Pipeline => {
+            let encoded = pipeline.expect("checked by header_for").encode(data, options.level)?;
+            writer.write_all(&(encoded.len() as u64).to_be_bytes())?;
+            writer.write_all(&encoded)?;
+        },
+    }
+
+    let blocks = match header.block_size {
+        Some(block_size) => checksum::block_checksums(data, block_size as usize),
+        None => Vec::new(),
+    };
+    write_trailer(writer, &header, crc32fast::hash(data), &blocks)?;
+    Ok(header)
+}
+
+// Builds the header for `options`, returning the pipeline too for `Algorithm::Pipeline`
+fn header_for(options: &Options, size: u64) -> Result<(Header, Option<&Pipeline>)> {
+    let mut header = Header::new(options.algorithm, size);
+    let pipeline = match (options.algorithm, &options.pipeline) {
+        (Algorithm::Pipeline, Some(pipeline)) => Some(pipeline),
+        (Algorithm::Pipeline, None) => return Err(Error::InvalidParams("A pipeline needs its stages".to_string())),
+        _ => None,
+    };
+    if let Some(pipeline) = pipeline {
+        header = header.with_pipeline(pipeline);
+    }
+    if let Some(block_size) = options.block_size {
+        header = header.with_block_checksums(block_size);
+    }
+    if let Some(metadata) = &options.metadata {
+        header = header.with_metadata(metadata.clone());
+    }
+    Ok((header, pipeline))
+}
+
+// Writes the block checksums and the content checksum the header asks for
+fn write_trailer<W: Write>(writer: &mut W, header: &Header, content: u32, blocks: &[u32]) -> Result<()> {
+    if header.block_size.is_some() {
+        for crc in blocks {
+            writer.write_all(&crc.to_be_bytes())?;
+        }
+    }
+    if header.flags & FLAG_CHECKSUM != 0 {
+        writer.write_all(&content.to_be_bytes())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Fails unless the reader held exactly `size` bytes, `read` of which are already read
+fn check_size<R: Read>(reader: &mut R, read: u64, size: u64) -> Result<()> {
+    if read != size || reader.read(&mut [0u8])? != 0 {
+        return Err(Error::InvalidParams(format!("Input is not the {} bytes it was said to be, it may have changed", size)));
+    }
+    Ok(())
+}
+
+/// Compresses a reader as it is read, for input that cannot be read into
+/// memory or rewound such as a pipe
+///
+/// Only LZSS works this way, so the algorithm in `options` must be LZSS.
+/// Per-block checksums are not supported either. The returned header
+/// holds the original size
+pub fn compress_stream<R: Read, W: Write>(reader: &mut R, writer: &mut W, options: &Options) -> Result<Header> {
+    if options.algorithm != Algorithm::Lzss || options.block_size.is_some() {
+        return Err(Error::InvalidParams("Only LZSS without block checksums can be streamed".to_string()));
+    }
+
+    let mut header = Header::new(options.algorithm, 0);
+    header.flags |= FLAG_STREAMED;
+    if let Some(metadata) = &options.metadata {
+        header = header.with_metadata(metadata.clone());
+    }
+    header.write_to(writer)?;
+
+    let mut reader = ChecksumReader::new(reader);
+    LZSS::write_stream(&mut reader, writer, options.level)?;
+
+    header.original_size = reader.read_bytes();
+    writer.write_all(&header.original_size.to_be_bytes())?;
+    writer.write_all(&reader.finish().to_be_bytes())?;
+    writer.flush()?;
+
+    Ok(header)
+}
+
+/// Reads a container and decodes it with the algorithm named in its header
+///
+/// Fails with [`Error::ChecksumMismatch`] naming the first bad block if
+/// the decoded data does not match the trailer. The decoded data has
+/// already been written by then
+pub fn decompress<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<Header> {
+    let mut header = Header::read_from(reader)?;
+    header.original_size = decode_body(&header, reader, writer)?;
+    Ok(header)
+}
+
+// Decodes and checks everything after the header, returning the original size
+fn decode_body<R: Read, W: Write>(header: &Header, reader: &mut R, writer: &mut W) -> Result<u64> {
+    let mut output = ChecksumWriter::new(writer, header.block_size);
+
+    let limit = match header.is_streamed() {
+        true => u64::MAX,
+        false => header.original_size,
+    };
+    match header.algorithm {
+        Algorithm::Lzss => LZSS::read_input_limited(reader, &mut output, limit)?,
+        Algorithm::Huffman => Huffman::decode_data(reader, &mut output, limit)?,
+        Algorithm::Lzh => Lzh::read_input_limited(reader, &mut output, limit)?,
+        Algorithm::Pipeline => {
+            let pipeline = header.pipeline().ok_or_else(|| invalid("Pipeline stages are missing"))?;
+            let len = read_u64(reader)?;
+            let mut encoded = Vec::new();
+            reader.take(len).read_to_end(&mut encoded)?;
+            if (encoded.len() as u64) < len {
+                return Err(invalid("Data is corrupt: it ends early"));
+            }
+            output.write_all(&pipeline.decode(&encoded)?)?;
+        },
+    }
+    output.flush()?;
+
+    let written = output.written();
+    let (content, blocks) = output.finish();
+
+    for block in 0..header.block_count() {
+        let expected = read_u32(reader)?;
+        let actual = blocks.get(block as usize).copied().unwrap_or(0);
+        if expected != actual {
+            return Err(ChecksumError { block: Some(block), expected, actual }.into());
+        }
+    }
+
+    let original_size = match header.is_streamed() {
+        true => read_u64(reader)?,
+        false => header.original_size,
+    };
+    if written != original_size {
+        return Err(invalid(&format!(
+            "Data is corrupt: decoded {} of {} bytes", written, original_size
+        )));
+    }
+
+    if header.flags & FLAG_CHECKSUM != 0 {
+        let expected = read_u32(reader)?;
+        if expected != content {
+            return Err(ChecksumError { block: None, expected, actual: content }.into());
+        }
+    }
+
+    Ok(original_size)
+}
+
+/// Compresses a file and records its name, modification time, mode and
+/// extended attributes in the header
+///
+/// An existing `output` is handled according to `policy`. Returns the
+/// header and the path that was written
+pub fn compress_file(input: &Path, output: &Path, options: &Options, policy: OutputPolicy) -> Result<(Header, PathBuf)> {
+    let options = options.clone().metadata(Metadata::from_path(input)?);
+    let reader = File::open(input)?;
+    let size = reader.metadata()?.len();
+
+    let mut file = fileops::create(output, policy)?;
+    let header = compress_sized(&mut BufReader::new(reader), &mut BufWriter::new(&mut file), size, &options)?;
+    Ok((header, file.commit()?))
+}
+
+/// Decompresses a file and puts back the metadata selected by `restore`
+///
+/// Without an `output` the file is written next to the input, under the
+/// stored name if `restore.name` is set and otherwise under the input's
+/// name without its extension. An existing output is handled according
+/// to `policy`. Returns the path that was written
+pub fn decompress_file(input: &Path, output: Option<&Path>, restore: &Restore, policy: OutputPolicy) -> Result<PathBuf> {
+    let mut reader = BufReader::new(File::open(input)?);
+    let header = Header::read_from(&mut reader)?;
+
+    let name = header.metadata.as_ref()
+        .map(|metadata| metadata.name.as_str())
+        .filter(|_| restore.name);
+    let mut file = fileops::create(&output_path(input, output, name)?, policy)?;
+    {
+        let mut writer = BufWriter::new(&mut file);
+        decode_body(&header, &mut reader, &mut writer)?;
+        writer.flush()?;
+    }
+
+    // Restored before the rename so the file appears complete
+    if let Some(metadata) = &header.metadata {
+        metadata.restore(file.temp_path(), restore)?;
+    }
+    file.commit()
+}
+
+// Where a decompressed file goes: `output` if given, else the stored name
+// next to the input, else the input without its extension
+pub(crate) fn output_path(input: &Path, output: Option<&Path>, stored_name: Option<&str>) -> Result<PathBuf> {
+    match (output, stored_name) {
+        (Some(output), _) => Ok(output.to_path_buf()),
+        (None, Some(name)) if !name.is_empty() => {
+            if Path::new(name).file_name() != Some(name.as_ref()) {
+                return Err(invalid("Stored file name is not a plain file name"));
+            }
+            Ok(input.with_file_name(name))
+        },
+        (None, _) => match input.extension() {
+            Some(_) => Ok(input.with_extension("")),
+            None => Err(Error::InvalidParams("Cannot tell the output name, give one".to_string())),
+        },
+    }
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    Ok(u64::from_be_bytes(read_array(reader)?))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    Ok(u32::from_be_bytes(read_array(reader)?))
+}
+
+// Everything read here is part of the format, so running out is corruption
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes).map_err(|e| truncated(e.into()))?;
+    Ok(bytes)
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::Corrupt(msg.to_string())
+}
+
+fn unsupported(msg: String) -> Error {
+    Error::Unsupported(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn round_trip(data: &[u8], algorithm: Algorithm) {
+        let mut compressed = Vec::new();
+        compress(&mut &data[..], &mut compressed, algorithm).unwrap();
+        assert_eq!(&compressed[..4], &MAGIC);
+
+        let mut output = Vec::new();
+        let header = decompress(&mut &compressed[..], &mut output).unwrap();
+        assert_eq!(header.algorithm, algorithm);
+        assert_eq!(header.original_size, data.len() as u64);
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for name in ["green.txt", "src/lzss.rs"] {
+            let data = std::fs::read(root.join(name)).unwrap();
+            round_trip(&data, Algorithm::Lzss);
+            round_trip(&data, Algorithm::Huffman);
+            round_trip(&data, Algorithm::Lzh);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_small() {
+        for data in [&b""[..], b"a", b"aaaa"] {
+            round_trip(data, Algorithm::Lzss);
+            round_trip(data, Algorithm::Huffman);
+            round_trip(data, Algorithm::Lzh);
+        }
+    }
+
+    #[test]
+    fn test_pipeline_round_trip() {
+        let data = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lzss.rs")).unwrap();
+        let pipeline: Pipeline = "delta,rle,lzss,huffman".parse().unwrap();
+
+        for data in [&data[..], b""] {
+            let options = Options::new(Algorithm::Pipeline).pipeline(pipeline.clone()).block_checksums(1000);
+            let mut compressed = Vec::new();
+            compress_with(&mut &data[..], &mut compressed, &options).unwrap();
+
+            // Trailing bytes are left alone, so the data length has to be right
+            compressed.extend_from_slice(b"after");
+            let mut reader = &compressed[..];
+            let mut output = Vec::new();
+            let header = decompress(&mut reader, &mut output).unwrap();
+            assert_eq!(header.pipeline(), Some(pipeline.clone()));
+            assert_eq!(header.codec(), "delta,rle,lzss,huffman");
+            assert_eq!(output, data);
+            assert_eq!(reader, b"after");
+        }
+
+        let err = compress_with(&mut &b"abc"[..], &mut Vec::new(), &Options::new(Algorithm::Pipeline)).unwrap_err();
+        assert!(matches!(err, Error::InvalidParams(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_rejects_unknown_stage() {
+        let pipeline: Pipeline = "lzss".parse().unwrap();
+        let mut bytes = Vec::new();
+        Header::new(Algorithm::Lzss, 0).with_pipeline(&pipeline).write_to(&mut bytes).unwrap();
+        assert_eq!(Header::read_from(&mut &bytes[..]).unwrap().pipeline(), Some(pipeline));
+        bytes[8] = 99;
+
+        let err = Header::read_from(&mut &bytes[..]).unwrap_err();
+        assert!(matches!(err, Error::Unsupported(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_block_checksums() {
+        let data = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lzss.rs")).unwrap();
+        let options = Options::new(Algorithm::Huffman).block_checksums(1000);
+
+        let mut compressed = Vec::new();
+        let header = compress_with(&mut &data[..], &mut compressed, &options).unwrap();
+        assert_eq!(header.block_count(), data.len().div_ceil(1000) as u64);
+
+        let mut output = Vec::new();
+        decompress(&mut &compressed[..], &mut output).unwrap();
+        assert_eq!(output, data);
+
+        // Break the stored checksum of block 2
+        let block_2 = compressed.len() - 4 * (header.block_count() as usize + 1) + 8;
+        compressed[block_2] ^= 0xFF;
+
+        let err = decompress(&mut &compressed[..], &mut Vec::new()).unwrap_err();
+        assert!(matches!(err, Error::ChecksumMismatch(ChecksumError { block: Some(2), .. })), "{:?}", err);
+    }
+
+    #[test]
+    fn test_detects_corrupt_data() {
+        let data = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("green.txt")).unwrap();
+
+        for algorithm in [Algorithm::Lzss, Algorithm::Huffman, Algorithm::Lzh] {
+            let mut compressed = Vec::new();
+            compress(&mut &data[..], &mut compressed, algorithm).unwrap();
+
+            let middle = compressed.len() / 2;
+            compressed[middle] ^= 0b0001_0000;
+
+            let err = decompress(&mut &compressed[..], &mut Vec::new()).unwrap_err();
+            assert!(matches!(err, Error::Corrupt(_) | Error::ChecksumMismatch(_)), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn test_content_checksum_mismatch() {
+        let mut compressed = Vec::new();
+        compress(&mut &b"hello hello hello"[..], &mut compressed, Algorithm::Lzss).unwrap();
+        let last = compressed.len() - 1;
+        compressed[last] ^= 1;
+
+        let err = decompress(&mut &compressed[..], &mut Vec::new()).unwrap_err();
+        assert!(matches!(err, Error::ChecksumMismatch(ChecksumError { block: None, .. })), "{:?}", err);
+    }
+
+    #[cfg(target_os = "linux")]
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("file_compressor_container_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_metadata_round_trip() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let dir = scratch("metadata");
+        let input = dir.join("notes.txt");
+        std::fs::write(&input, b"some notes, some more notes").unwrap();
+        std::fs::set_permissions(&input, std::fs::Permissions::from_mode(0o751)).unwrap();
+        let mtime = UNIX_EPOCH + Duration::new(1_600_000_000, 250_000_000);
+        File::options().write(true).open(&input).unwrap().set_modified(mtime).unwrap();
+        // Not every filesystem has user xattrs
+        let xattrs = xattr::set(&input, "user.origin", b"test").is_ok();
+
+        let compressed = dir.join("notes.fcmp");
+        let (header, _) = compress_file(&input, &compressed, &Options::new(Algorithm::Lzss), OutputPolicy::FailIfExists).unwrap();
+        assert_eq!(header.metadata.as_ref().unwrap().name, "notes.txt");
+        std::fs::remove_file(&input).unwrap();
+
+        let output = decompress_file(&compressed, None, &Restore::all(), OutputPolicy::FailIfExists).unwrap();
+        assert_eq!(output, input);
+        assert_eq!(std::fs::read(&output).unwrap(), b"some notes, some more notes");
+
+        let metadata = std::fs::metadata(&output).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o751);
+        assert_eq!(metadata.modified().unwrap(), mtime);
+        if xattrs {
+            assert_eq!(xattr::get(&output, "user.origin").unwrap(), Some(b"test".to_vec()));
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_metadata_skip_restore() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let dir = scratch("skip");
+        let input = dir.join("script.sh");
+        std::fs::write(&input, b"echo hi").unwrap();
+        std::fs::set_permissions(&input, std::fs::Permissions::from_mode(0o700)).unwrap();
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options().write(true).open(&input).unwrap().set_modified(mtime).unwrap();
+
+        let compressed = dir.join("packed.fcmp");
+        compress_file(&input, &compressed, &Options::new(Algorithm::Huffman), OutputPolicy::FailIfExists).unwrap();
+
+        let restore = Restore { mode: false, mtime: false, ..Restore::all() };
+        let output = decompress_file(&compressed, Some(&dir.join("plain")), &restore, OutputPolicy::FailIfExists).unwrap();
+        assert_eq!(output, dir.join("plain"));
+
+        let metadata = std::fs::metadata(&output).unwrap();
+        assert_ne!(metadata.permissions().mode() & 0o7777, 0o700);
+        assert_ne!(metadata.modified().unwrap(), mtime);
+
+        // Without the stored name the extension is dropped instead
+        let output = decompress_file(&compressed, None, &Restore { name: false, ..Restore::none() }, OutputPolicy::FailIfExists).unwrap();
+        assert_eq!(output, dir.join("packed"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stream_round_trip() {
+        let data = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lzss.rs")).unwrap();
+
+        for data in [&data[..], b"", b"a"] {
+            let mut compressed = Vec::new();
+            let header = compress_stream(&mut &data[..], &mut compressed, &Options::new(Algorithm::Lzss)).unwrap();
+            assert!(header.is_streamed());
+            assert_eq!(header.original_size, data.len() as u64);
+
+            // Trailing bytes are left alone, so the end has to come from the marker
+            compressed.extend_from_slice(b"after");
+            let mut reader = &compressed[..];
+            let mut output = Vec::new();
+            let header = decompress(&mut reader, &mut output).unwrap();
+            assert_eq!(header.original_size, data.len() as u64);
+            assert_eq!(output, data);
+            assert_eq!(reader, b"after");
+        }
+
+        let options = Options::new(Algorithm::Huffman);
+        let err = compress_stream(&mut &b"abc"[..], &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Error::InvalidParams(_)), "{:?}", err);
+    }
+
+    // Hands out at most 64 bytes per read, like a pipe
+    struct ShortReader<'a>(&'a [u8]);
+
+    impl Read for ShortReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(64);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_stream_short_reads() {
+        let data = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("green.txt")).unwrap();
+        let options = Options::new(Algorithm::Lzss);
+
+        let mut expected = Vec::new();
+        compress_stream(&mut &data[..], &mut expected, &options).unwrap();
+
+        // Matches still reach across reads, so the output is the same
+        let mut compressed = Vec::new();
+        compress_stream(&mut ShortReader(&data), &mut compressed, &options).unwrap();
+        assert_eq!(compressed, expected);
+    }
+
+    #[test]
+    fn test_sized_matches_buffered() {
+        let data = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lzss.rs")).unwrap();
+        let pipeline: Pipeline = "delta,lzss".parse().unwrap();
+
+        for options in [
+            Options::new(Algorithm::Lzss).block_checksums(1000),
+            Options::new(Algorithm::Huffman),
+            Options::new(Algorithm::Lzh).level(3),
+            Options::new(Algorithm::Pipeline).pipeline(pipeline.clone()),
+        ] {
+            let mut expected = Vec::new();
+            compress_with(&mut &data[..], &mut expected, &options).unwrap();
+
+            let mut compressed = Vec::new();
+            compress_sized(&mut ShortReader(&data), &mut compressed, data.len() as u64, &options).unwrap();
+            assert_eq!(compressed, expected, "{:?}", options.algorithm);
+        }
+    }
+
+    #[test]
+    fn test_sized_rejects_wrong_size() {
+        for algorithm in [Algorithm::Lzss, Algorithm::Huffman] {
+            for size in [2, 4] {
+                let err = compress_sized(&mut &b"abc"[..], &mut Vec::new(), size, &Options::new(algorithm)).unwrap_err();
+                assert!(matches!(err, Error::InvalidParams(_)), "{:?}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let metadata = Metadata { name: "a.txt".to_string(), mtime: 5, mode: 0o644, ..Default::default() };
+        let header = Header::new(Algorithm::Lzss, 1234).with_block_checksums(4096).with_metadata(metadata);
+        let mut bytes = Vec::new();
+        header.write_to(&mut bytes).unwrap();
+
+        assert_eq!(Header::read_from(&mut &bytes[..]).unwrap(), header);
+    }
+
+    #[test]
+    fn test_rejects_bad_magic() {
+        let err = Header::read_from(&mut &b"PK\x03\x04\x01\x01\x00\x00"[..]).unwrap_err();
+        assert!(matches!(err, Error::Corrupt(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_rejects_newer_version() {
+        let mut bytes = Vec::new();
+        Header::new(Algorithm::Huffman, 0).write_to(&mut bytes).unwrap();
+        bytes[4] = VERSION + 1;
+
+        let err = Header::read_from(&mut &bytes[..]).unwrap_err();
+        assert!(matches!(err, Error::Unsupported(_)), "{:?}", err);
+        assert!(err.to_string().contains("version"));
+    }
+
+    #[test]
+    fn test_rejects_unknown_algorithm() {
+        let mut bytes = Vec::new();
+        Header::new(Algorithm::Huffman, 0).write_to(&mut bytes).unwrap();
+        bytes[5] = 99;
+
+        let err = Header::read_from(&mut &bytes[..]).unwrap_err();
+        assert!(matches!(err, Error::Unsupported(_)), "{:?}", err);
+        assert!(err.to_string().contains("algorithm id 99"));
+    }
+}
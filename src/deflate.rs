@@ -0,0 +1,814 @@
+//! Raw DEFLATE (RFC 1951), the compressed data inside zlib, gzip and zip files
+//!
+//! The encoder finds matches with the LZSS match finder, so its matches
+//! are shorter and nearer than DEFLATE allows, and picks whichever of a
+//! stored, fixed or dynamic huffman block is smallest. The decoder reads
+//! every valid stream, whatever made it
+
+use std::io::{self, Read, Write};
+
+use crate::bitreader::BitReader;
+use crate::bitwriter::{BitOrder, BitWriter};
+use crate::canonical::{code_lengths, CanonicalCode, MAX_CODE_LENGTH};
+use crate::codec::{Codec, Encoder};
+use crate::error::{truncated, Error, Result};
+use crate::lzss::{self, Output, LZSS};
+
+// Furthest back a match can reach
+const WINDOW: usize = 32768;
+
+// Tokens in one huffman block, and bytes in one stored block
+const BLOCK_TOKENS: usize = 1 << 14;
+const MAX_STORED: usize = 65535;
+
+const STORED: u32 = 0;
+const FIXED: u32 = 1;
+const DYNAMIC: u32 = 2;
+
+// Literals are symbols 0 to 255, then the end of a block, then lengths
+const END_OF_BLOCK: usize = 256;
+const LITERAL_SYMBOLS: usize = 286;
+const DISTANCE_SYMBOLS: usize = 30;
+
+// Lengths 3 to 258 and distances 1 to 32768 as a first value and a number of extra bits
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; DISTANCE_SYMBOLS] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; DISTANCE_SYMBOLS] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+// The code lengths of a dynamic block are themselves huffman coded, with
+// three symbols for runs. The lengths of that code are sent in this order
+const CODE_LENGTH_SYMBOLS: usize = 19;
+const MAX_CODE_LENGTH_LENGTH: u8 = 7;
+const CODE_LENGTH_ORDER: [usize; CODE_LENGTH_SYMBOLS] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+const REPEAT_PREVIOUS: u8 = 16;
+const REPEAT_ZERO: u8 = 17;
+const REPEAT_ZERO_LONG: u8 = 18;
+
+pub struct Deflate;
+
+impl Deflate {
+    /// Compresses everything in the reader into a raw DEFLATE stream
+    pub fn write_output<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<()> {
+        Self::write_output_level(reader, writer, lzss::MAX_LEVEL)
+    }
+
+    /// Does the same thing as `write_output` but only searches as far
+    /// back as `level` allows, like [`LZSS::write_output_level`]
+    pub fn write_output_level<R: Read, W: Write>(reader: &mut R, writer: &mut W, level: u8) -> Result<()> {
+        write_chunks(reader, writer, level, lzss::BUFFER_SIZE)
+    }
+
+    /// Decodes a raw DEFLATE stream
+    ///
+    /// Only the bytes that make up the stream are consumed, the rest of
+    /// the last byte is padding
+    pub fn read_input<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<()> {
+        Self::read_input_limited(reader, writer, u64::MAX)
+    }
+
+    /// Does the same thing as `read_input` but fails if the stream holds
+    /// more than `limit` bytes
+    pub fn read_input_limited<R: Read, W: Write>(reader: &mut R, writer: &mut W, limit: u64) -> Result<()> {
+        let mut reader = BitReader::with_order(reader, BitOrder::LsbFirst);
+        let mut output = History::new(writer, limit);
+
+        while !read_block(&mut reader, &mut output)? {}
+        output.write_pending()
+    }
+}
+
+impl Codec for Deflate {
+    type Encoder<W: Write> = DeflateEncoder<W>;
+    type Decoder<R: Read> = DeflateDecoder<R>;
+
+    fn encoder<W: Write>(&self, writer: W) -> DeflateEncoder<W> {
+        DeflateEncoder {
+            writer: BitWriter::with_order(writer, BitOrder::LsbFirst),
+            chunk: Vec::new(),
+        }
+    }
+
+    fn decoder<R: Read>(&self, reader: R) -> DeflateDecoder<R> {
+        DeflateDecoder {
+            reader: BitReader::with_order(reader, BitOrder::LsbFirst),
+            output: History::new(Vec::new(), u64::MAX),
+            pos: 0,
+            done: false,
+        }
+    }
+}
+
+/// Compresses everything written to it into a raw DEFLATE stream
+///
+/// The output is the same as [`Deflate::write_output`]. A full chunk is
+/// held until more data or [`Encoder::finish`] tells whether it is the last
+pub struct DeflateEncoder<W: Write> {
+    writer: BitWriter<W>,
+    chunk: Vec<u8>,
+}
+
+impl<W: Write> DeflateEncoder<W> {
+    fn write_chunk(&mut self, last: bool) -> Result<()> {
+        write_chunk(&self.chunk, &mut self.writer, lzss::MAX_LEVEL, last)?;
+        self.chunk.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for DeflateEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.chunk.len() == lzss::BUFFER_SIZE && !buf.is_empty() {
+            self.write_chunk(false)?;
+        }
+        let len = buf.len().min(lzss::BUFFER_SIZE - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    // Encodes what is buffered so far as blocks that are not the last,
+    // which costs some compression as later data cannot refer back into it
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.chunk.is_empty() {
+            self.write_chunk(false)?;
+        }
+        self.writer.get_mut().flush()
+    }
+}
+
+impl<W: Write> Encoder<W> for DeflateEncoder<W> {
+    fn finish(mut self) -> Result<W> {
+        self.write_chunk(true)?;
+        self.writer.into_inner()
+    }
+}
+
+/// Decompresses a raw DEFLATE stream, such as one written by [`DeflateEncoder`]
+pub struct DeflateDecoder<R: Read> {
+    reader: BitReader<R>,
+    // Its writer holds the output of the last block that has not been read yet
+    output: History<Vec<u8>>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> Read for DeflateDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.output.writer.len() {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            self.output.writer.clear();
+            self.pos = 0;
+            self.done = read_block(&mut self.reader, &mut self.output)?;
+            self.output.write_pending()?;
+        }
+
+        let decoded = &self.output.writer;
+        let len = buf.len().min(decoded.len() - self.pos);
+        buf[..len].copy_from_slice(&decoded[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+// Encodes the reader `chunk_size` bytes at a time. Reading one chunk ahead
+// tells which block is the last
+fn write_chunks<R: Read, W: Write>(reader: &mut R, writer: &mut W, level: u8, chunk_size: usize) -> Result<()> {
+    let mut writer = BitWriter::with_order(writer, BitOrder::LsbFirst);
+    let mut chunk = read_chunk(reader, chunk_size)?;
+
+    loop {
+        let next = match chunk.len() < chunk_size {
+            true => Vec::new(),
+            false => read_chunk(reader, chunk_size)?,
+        };
+        let last = next.is_empty();
+        write_chunk(&chunk, &mut writer, level, last)?;
+        if last {
+            return writer.flush();
+        }
+        chunk = next;
+    }
+}
+
+// Reads until `size` bytes or the end of the reader
+fn read_chunk<R: Read>(reader: &mut R, size: usize) -> Result<Vec<u8>> {
+    let mut chunk = vec![0u8; size];
+    let len = lzss::fill_buffer(reader, &mut chunk)?;
+    chunk.truncate(len);
+    Ok(chunk)
+}
+
+// Matches never reach back into an earlier chunk
+fn write_chunk<W: Write>(data: &[u8], writer: &mut BitWriter<W>, level: u8, last: bool) -> Result<()> {
+    let tokens = LZSS::encode_level(data, level);
+    if tokens.is_empty() {
+        return write_block(&[], &[], writer, last);
+    }
+
+    let mut start = 0;
+    let blocks = tokens.chunks(BLOCK_TOKENS).count();
+    for (i, block) in tokens.chunks(BLOCK_TOKENS).enumerate() {
+        let len: usize = block.iter()
+            .map(|token| match *token {
+                Output::Literal(_) => 1,
+                Output::Reference { length, .. } => length as usize,
+            })
+            .sum();
+        write_block(block, &data[start..start + len], writer, last && i == blocks - 1)?;
+        start += len;
+    }
+    Ok(())
+}
+
+// Writes the tokens as whichever kind of block is smallest. `data` is
+// what they decode to, for a stored block
+fn write_block<W: Write>(tokens: &[Output], data: &[u8], writer: &mut BitWriter<W>, last: bool) -> Result<()> {
+    let mut literal_freqs = vec![0usize; LITERAL_SYMBOLS];
+    let mut distance_freqs = vec![0usize; DISTANCE_SYMBOLS];
+    for token in tokens {
+        match *token {
+            Output::Literal(byte) => literal_freqs[byte as usize] += 1,
+            Output::Reference { offset, length } => {
+                literal_freqs[END_OF_BLOCK + 1 + length_index(length as u16)] += 1;
+                distance_freqs[distance_index(offset)] += 1;
+            },
+        }
+    }
+    literal_freqs[END_OF_BLOCK] = 1;
+
+    let (fixed_literals, fixed_distances) = fixed_codes();
+    let dynamic = DynamicHeader::new(&literal_freqs, &distance_freqs)?;
+
+    let fixed_cost = tokens_cost(&literal_freqs, &distance_freqs, fixed_literals.lengths(), fixed_distances.lengths());
+    let dynamic_cost = dynamic.cost() + tokens_cost(&literal_freqs, &distance_freqs, dynamic.literals.lengths(), dynamic.distances.lengths());
+    // Each stored block pads to a byte and has a 4 byte length
+    let stored_cost = data.len().div_ceil(MAX_STORED).max(1) * (3 + 7 + 32) + 8 * data.len();
+
+    if stored_cost < fixed_cost.min(dynamic_cost) {
+        return write_stored(data, writer, last);
+    }
+
+    writer.write_bits_le(last as u32, 1)?;
+    if fixed_cost <= dynamic_cost {
+        writer.write_bits_le(FIXED, 2)?;
+        write_tokens(tokens, &fixed_literals, &fixed_distances, writer)
+    } else {
+        writer.write_bits_le(DYNAMIC, 2)?;
+        dynamic.write_to(writer)?;
+        write_tokens(tokens, &dynamic.literals, &dynamic.distances, writer)
+    }
+}
+
+fn write_stored<W: Write>(data: &[u8], writer: &mut BitWriter<W>, last: bool) -> Result<()> {
+    let blocks = data.chunks(MAX_STORED).count().max(1);
+    for i in 0..blocks {
+        let part = &data[(i * MAX_STORED).min(data.len())..((i + 1) * MAX_STORED).min(data.len())];
+        writer.write_bits_le((last && i == blocks - 1) as u32, 1)?;
+        writer.write_bits_le(STORED, 2)?;
+        writer.align()?;
+        writer.write_bits_le(part.len() as u32, 16)?;
+        writer.write_bits_le(!part.len() as u32 & 0xFFFF, 16)?;
+        for &byte in part {
+            writer.write_u8(byte)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_tokens<W: Write>(
+    tokens: &[Output],
+    literals: &CanonicalCode,
+    distances: &CanonicalCode,
+    writer: &mut BitWriter<W>
+) -> Result<()> {
+    for token in tokens {
+        match *token {
+            Output::Literal(byte) => literals.write_symbol(byte as usize, writer)?,
+            Output::Reference { offset, length } => {
+                let index = length_index(length as u16);
+                literals.write_symbol(END_OF_BLOCK + 1 + index, writer)?;
+                writer.write_bits_le((length as u16 - LENGTH_BASE[index]) as u32, LENGTH_EXTRA[index])?;
+
+                let index = distance_index(offset);
+                distances.write_symbol(index, writer)?;
+                writer.write_bits_le((offset - DISTANCE_BASE[index]) as u32, DISTANCE_EXTRA[index])?;
+            },
+        }
+    }
+    literals.write_symbol(END_OF_BLOCK, writer)
+}
+
+// Bits the tokens counted in the frequencies take with these code lengths
+fn tokens_cost(literal_freqs: &[usize], distance_freqs: &[usize], literal_lengths: &[u8], distance_lengths: &[u8]) -> usize {
+    let literals: usize = literal_freqs.iter()
+        .enumerate()
+        .map(|(symbol, freq)| {
+            let extra = symbol.checked_sub(END_OF_BLOCK + 1).map_or(0, |index| LENGTH_EXTRA[index]);
+            freq * (literal_lengths[symbol] + extra) as usize
+        })
+        .sum();
+    let distances: usize = distance_freqs.iter()
+        .enumerate()
+        .map(|(symbol, freq)| freq * (distance_lengths[symbol] + DISTANCE_EXTRA[symbol]) as usize)
+        .sum();
+    3 + literals + distances
+}
+
+fn length_index(length: u16) -> usize {
+    LENGTH_BASE.partition_point(|&base| base <= length) - 1
+}
+
+fn distance_index(distance: u16) -> usize {
+    DISTANCE_BASE.partition_point(|&base| base <= distance) - 1
+}
+
+// The fixed codes of section 3.2.6. Symbols 286 and 287 and distances
+// 30 and 31 have codes but never appear
+fn fixed_codes() -> (CanonicalCode, CanonicalCode) {
+    let mut literals = [8u8; 288];
+    literals[144..256].fill(9);
+    literals[256..280].fill(7);
+    let literals = CanonicalCode::from_lengths(&literals).expect("the fixed code is valid");
+    let distances = CanonicalCode::from_lengths(&[5; 32]).expect("the fixed code is valid");
+    (literals, distances)
+}
+
+// The codes of a dynamic block and how their lengths are written
+struct DynamicHeader {
+    literals: CanonicalCode,
+    distances: CanonicalCode,
+    code_lengths: CanonicalCode,
+    // Symbols of the code length code, each with the value of its extra bits
+    runs: Vec<(u8, u8)>,
+    literal_count: usize,
+    distance_count: usize,
+    code_length_count: usize,
+}
+
+impl DynamicHeader {
+    fn new(literal_freqs: &[usize], distance_freqs: &[usize]) -> Result<DynamicHeader> {
+        let literal_lengths = code_lengths(literal_freqs, MAX_CODE_LENGTH);
+        // A block of only literals still sends one distance code
+        let mut distance_lengths = code_lengths(distance_freqs, MAX_CODE_LENGTH);
+        if distance_lengths.iter().all(|&len| len == 0) {
+            distance_lengths[0] = 1;
+        }
+
+        let literal_count = used(&literal_lengths).max(END_OF_BLOCK + 1);
+        let distance_count = used(&distance_lengths);
+        let mut lengths = literal_lengths[..literal_count].to_vec();
+        lengths.extend_from_slice(&distance_lengths[..distance_count]);
+        let runs = runs(&lengths);
+
+        let mut freqs = vec![0usize; CODE_LENGTH_SYMBOLS];
+        for &(symbol, _) in &runs {
+            freqs[symbol as usize] += 1;
+        }
+        // zlib wants the code length code complete, which takes two symbols
+        while freqs.iter().filter(|&&freq| freq > 0).count() < 2 {
+            let unused = freqs.iter().position(|&freq| freq == 0).expect("19 symbols");
+            freqs[unused] = 1;
+        }
+        let code_lengths = CanonicalCode::from_freqs(&freqs, MAX_CODE_LENGTH_LENGTH);
+        let code_length_count = CODE_LENGTH_ORDER.iter()
+            .rposition(|&symbol| code_lengths.lengths()[symbol] > 0)
+            .map_or(0, |last| last + 1)
+            .max(4);
+
+        Ok(DynamicHeader {
+            literals: CanonicalCode::from_lengths(&literal_lengths)?,
+            distances: CanonicalCode::from_lengths(&distance_lengths)?,
+            code_lengths,
+            runs,
+            literal_count,
+            distance_count,
+            code_length_count,
+        })
+    }
+
+    // Bits the header takes
+    fn cost(&self) -> usize {
+        let runs: usize = self.runs.iter()
+            .map(|&(symbol, _)| (self.code_lengths.lengths()[symbol as usize] + extra_bits(symbol)) as usize)
+            .sum();
+        5 + 5 + 4 + 3 * self.code_length_count + runs
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut BitWriter<W>) -> Result<()> {
+        writer.write_bits_le((self.literal_count - END_OF_BLOCK - 1) as u32, 5)?;
+        writer.write_bits_le((self.distance_count - 1) as u32, 5)?;
+        writer.write_bits_le((self.code_length_count - 4) as u32, 4)?;
+        for &symbol in &CODE_LENGTH_ORDER[..self.code_length_count] {
+            writer.write_bits_le(self.code_lengths.lengths()[symbol] as u32, 3)?;
+        }
+        for &(symbol, extra) in &self.runs {
+            self.code_lengths.write_symbol(symbol as usize, writer)?;
+            writer.write_bits_le(extra as u32, extra_bits(symbol))?;
+        }
+        Ok(())
+    }
+}
+
+// Number of symbols up to the last that has a code
+fn used(lengths: &[u8]) -> usize {
+    lengths.iter().rposition(|&len| len > 0).map_or(0, |last| last + 1)
+}
+
+fn extra_bits(symbol: u8) -> u8 {
+    match symbol {
+        REPEAT_PREVIOUS => 2,
+        REPEAT_ZERO => 3,
+        REPEAT_ZERO_LONG => 7,
+        _ => 0,
+    }
+}
+
+// Replaces runs in a list of code lengths with the repeat symbols
+fn runs(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let len = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == len).count();
+        match (len, run) {
+            (0, 11..) => {
+                let run = run.min(138);
+                runs.push((REPEAT_ZERO_LONG, (run - 11) as u8));
+                i += run;
+            },
+            (0, 3..) => {
+                runs.push((REPEAT_ZERO, (run - 3) as u8));
+                i += run;
+            },
+            (_, 4..) => {
+                runs.push((len, 0));
+                let repeat = (run - 1).min(6);
+                runs.push((REPEAT_PREVIOUS, (repeat - 3) as u8));
+                i += 1 + repeat;
+            },
+            _ => {
+                runs.push((len, 0));
+                i += 1;
+            },
+        }
+    }
+    runs
+}
+
+// Decodes one block, returning true if it is the last
+fn read_block<R: Read, W: Write>(reader: &mut BitReader<R>, output: &mut History<W>) -> Result<bool> {
+    let last = read_bits(reader, 1)? == 1;
+    match read_bits(reader, 2)? {
+        STORED => read_stored(reader, output)?,
+        FIXED => {
+            let (literals, distances) = fixed_codes();
+            read_compressed(reader, output, &literals, &distances)?;
+        },
+        DYNAMIC => {
+            let (literals, distances) = read_dynamic_codes(reader)?;
+            read_compressed(reader, output, &literals, &distances)?;
+        },
+        _ => return Err(Error::Corrupt("Data is corrupt: reserved DEFLATE block type".to_string())),
+    }
+    Ok(last)
+}
+
+fn read_bits<R: Read>(reader: &mut BitReader<R>, count: u8) -> Result<u32> {
+    reader.read_bits_le(count).map_err(truncated)
+}
+
+fn read_stored<R: Read, W: Write>(reader: &mut BitReader<R>, output: &mut History<W>) -> Result<()> {
+    reader.align();
+    let len = read_bits(reader, 16)?;
+    let nlen = read_bits(reader, 16)?;
+    if len != !nlen & 0xFFFF {
+        return Err(Error::Corrupt("Data is corrupt: stored block length does not match its complement".to_string()));
+    }
+    for _ in 0..len {
+        output.push(reader.read_byte().map_err(truncated)?)?;
+    }
+    Ok(())
+}
+
+fn read_dynamic_codes<R: Read>(reader: &mut BitReader<R>) -> Result<(CanonicalCode, CanonicalCode)> {
+    let literal_count = read_bits(reader, 5)? as usize + END_OF_BLOCK + 1;
+    let distance_count = read_bits(reader, 5)? as usize + 1;
+    let code_length_count = read_bits(reader, 4)? as usize + 4;
+    if literal_count > LITERAL_SYMBOLS || distance_count > DISTANCE_SYMBOLS {
+        return Err(Error::Corrupt(format!(
+            "Data is corrupt: {} literal and {} distance codes", literal_count, distance_count
+        )));
+    }
+
+    let mut code_lengths = [0u8; CODE_LENGTH_SYMBOLS];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = read_bits(reader, 3)? as u8;
+    }
+    let code_lengths = CanonicalCode::from_lengths(&code_lengths)?;
+
+    let count = literal_count + distance_count;
+    let mut lengths = Vec::with_capacity(count);
+    while lengths.len() < count {
+        let symbol = code_lengths.read_symbol(reader)? as u8;
+        let (len, repeat) = match symbol {
+            REPEAT_PREVIOUS => match lengths.last() {
+                Some(&previous) => (previous, 3 + read_bits(reader, 2)?),
+                None => return Err(Error::Corrupt("Data is corrupt: repeat of no code length".to_string())),
+            },
+            REPEAT_ZERO => (0, 3 + read_bits(reader, 3)?),
+            REPEAT_ZERO_LONG => (0, 11 + read_bits(reader, 7)?),
+            len => (len, 1),
+        };
+        lengths.resize(lengths.len() + repeat as usize, len);
+    }
+    if lengths.len() > count {
+        return Err(Error::Corrupt("Data is corrupt: code lengths run past the table".to_string()));
+    }
+    if lengths[END_OF_BLOCK] == 0 {
+        return Err(Error::Corrupt("Data is corrupt: no code for the end of the block".to_string()));
+    }
+
+    let literals = CanonicalCode::from_lengths(&lengths[..literal_count])?;
+    let distances = CanonicalCode::from_lengths(&lengths[literal_count..])?;
+    Ok((literals, distances))
+}
+
+fn read_compressed<R: Read, W: Write>(
+    reader: &mut BitReader<R>,
+    output: &mut History<W>,
+    literals: &CanonicalCode,
+    distances: &CanonicalCode
+) -> Result<()> {
+    loop {
+        match literals.read_symbol(reader)? {
+            END_OF_BLOCK => return Ok(()),
+            byte @ 0..END_OF_BLOCK => output.push(byte as u8)?,
+            symbol if symbol < LITERAL_SYMBOLS => {
+                let index = symbol - END_OF_BLOCK - 1;
+                let length = LENGTH_BASE[index] as usize + read_bits(reader, LENGTH_EXTRA[index])? as usize;
+
+                let index = distances.read_symbol(reader)?;
+                if index >= DISTANCE_SYMBOLS {
+                    return Err(Error::Corrupt(format!("Data is corrupt: distance symbol {}", index)));
+                }
+                let distance = DISTANCE_BASE[index] as usize + read_bits(reader, DISTANCE_EXTRA[index])? as usize;
+                output.copy(distance, length)?;
+            },
+            symbol => return Err(Error::Corrupt(format!("Data is corrupt: length symbol {}", symbol))),
+        }
+    }
+}
+
+// Decoded bytes that are not written yet, and the window behind them
+struct History<W: Write> {
+    writer: W,
+    data: Vec<u8>,
+    unwritten: usize,
+    room: u64,
+}
+
+impl<W: Write> History<W> {
+    fn new(writer: W, limit: u64) -> History<W> {
+        History {
+            writer,
+            data: Vec::with_capacity(2 * WINDOW),
+            unwritten: 0,
+            room: limit,
+        }
+    }
+
+    fn push(&mut self, byte: u8) -> Result<()> {
+        if self.room == 0 {
+            return Err(Error::Corrupt("Data is corrupt: it holds more than expected".to_string()));
+        }
+        self.room -= 1;
+        self.data.push(byte);
+
+        if self.data.len() == 3 * WINDOW {
+            self.writer.write_all(&self.data[self.unwritten..])?;
+            self.data.drain(..2 * WINDOW);
+            self.unwritten = self.data.len();
+        }
+        Ok(())
+    }
+
+    fn copy(&mut self, distance: usize, length: usize) -> Result<()> {
+        if distance > self.data.len() {
+            return Err(Error::Corrupt(format!(
+                "Data is corrupt: reference to {} bytes at {} back with {} decoded", length, distance, self.data.len()
+            )));
+        }
+        for _ in 0..length {
+            self.push(self.data[self.data.len() - distance])?;
+        }
+        Ok(())
+    }
+
+    // Writes everything decoded so far
+    fn write_pending(&mut self) -> Result<()> {
+        self.writer.write_all(&self.data[self.unwritten..])?;
+        self.unwritten = self.data.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn root() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    }
+
+    fn vector(name: &str) -> Vec<u8> {
+        std::fs::read(root().join("testdata/deflate").join(name)).unwrap()
+    }
+
+    fn inflate(compressed: &[u8]) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        Deflate::read_input(&mut &compressed[..], &mut output)?;
+        Ok(output)
+    }
+
+    fn deflate(data: &[u8], level: u8) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        Deflate::write_output_level(&mut &data[..], &mut compressed, level).unwrap();
+        compressed
+    }
+
+    // The type of the first block
+    fn block_type(compressed: &[u8]) -> u32 {
+        (compressed[0] >> 1 & 0b11) as u32
+    }
+
+    #[test]
+    fn test_zlib_vectors() {
+        let green = std::fs::read(root().join("green.txt")).unwrap();
+        let words = vector("words.txt");
+        let runs = vector("runs.bin");
+
+        let vectors: [(&str, &[u8]); 7] = [
+            ("empty.deflate", b""),
+            ("green.stored.deflate", &green),
+            ("green.fixed.deflate", &green),
+            ("words.dynamic.deflate", &words),
+            ("words.huffman.deflate", &words),
+            ("words.flush.deflate", &words),
+            ("runs.rle.deflate", &runs),
+        ];
+        for (name, expected) in vectors {
+            assert_eq!(inflate(&vector(name)).unwrap(), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_stops_at_end_of_stream() {
+        let mut compressed = vector("green.fixed.deflate");
+        compressed.extend_from_slice(b"trailer");
+
+        let mut reader = &compressed[..];
+        Deflate::read_input(&mut reader, &mut Vec::new()).unwrap();
+        assert_eq!(reader, b"trailer");
+    }
+
+    #[test]
+    fn test_codec_matches_read_and_write() {
+        let data = vector("words.txt");
+        let mut encoder = Deflate.encoder(Vec::new());
+        for chunk in data.chunks(1000) {
+            encoder.write_all(chunk).unwrap();
+        }
+        assert_eq!(encoder.finish().unwrap(), deflate(&data, lzss::MAX_LEVEL));
+
+        // Flushing ends a run of blocks early, the stream still decodes
+        let mut encoder = Deflate.encoder(Vec::new());
+        encoder.write_all(&data[..1000]).unwrap();
+        encoder.flush().unwrap();
+        encoder.write_all(&data[1000..]).unwrap();
+        assert_eq!(inflate(&encoder.finish().unwrap()).unwrap(), data);
+
+        // Decoded a few bytes at a time, stopping where the stream ends
+        let mut compressed = vector("words.dynamic.deflate");
+        compressed.extend_from_slice(b"trailer");
+        let mut reader = &compressed[..];
+        let mut decoder = Deflate.decoder(&mut reader);
+        let mut output = Vec::new();
+        let mut chunk = [0u8; 5];
+        loop {
+            match decoder.read(&mut chunk).unwrap() {
+                0 => break,
+                len => output.extend_from_slice(&chunk[..len]),
+            }
+        }
+        assert_eq!(output, data);
+        assert_eq!(reader, b"trailer");
+    }
+
+    #[test]
+    fn test_matches_zlib_fixed_block() {
+        // zlib's raw DEFLATE of "hello" with Z_FIXED, written the same way here
+        let zlib = [0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00];
+        assert_eq!(inflate(&zlib).unwrap(), b"hello");
+        assert_eq!(deflate(b"hello", lzss::MAX_LEVEL), zlib);
+        assert_eq!(deflate(b"", lzss::MAX_LEVEL), vector("empty.deflate"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let sample = std::fs::read(root().join("src/lzss.rs")).unwrap();
+        for data in [&sample[..], b"", b"a", b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"] {
+            assert_eq!(inflate(&deflate(data, lzss::MAX_LEVEL)).unwrap(), data);
+        }
+        for name in ["words.txt", "runs.bin"] {
+            let data = vector(name);
+            assert_eq!(inflate(&deflate(&data, lzss::MIN_LEVEL)).unwrap(), data, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_picks_block_type() {
+        let mut state = 0x2545F491u32;
+        let noise: Vec<u8> = (0..5000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let compressed = deflate(&noise, lzss::MIN_LEVEL);
+        assert_eq!(block_type(&compressed), STORED);
+        assert_eq!(compressed.len(), noise.len() + 5);
+
+        assert_eq!(block_type(&deflate(b"hello", lzss::MAX_LEVEL)), FIXED);
+        assert_eq!(block_type(&deflate(&vector("words.txt"), lzss::MIN_LEVEL)), DYNAMIC);
+    }
+
+    #[test]
+    fn test_many_chunks_and_blocks() {
+        let data = vector("words.txt");
+        let mut compressed = Vec::new();
+        write_chunks(&mut &data[..], &mut compressed, lzss::MIN_LEVEL, 7000).unwrap();
+        assert_eq!(inflate(&compressed).unwrap(), data);
+
+        // Stored blocks hold at most 65535 bytes
+        let mut compressed = Vec::new();
+        write_stored(&data, &mut BitWriter::with_order(&mut compressed, BitOrder::LsbFirst), true).unwrap();
+        assert_eq!(compressed.len(), data.len() + 2 * 5);
+        assert_eq!(inflate(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn test_runs() {
+        let lengths = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, 8, 8, 8, 8, 8, 8, 8, 3, 0, 0, 0];
+        let runs = runs(&lengths);
+        assert_eq!(runs, [(REPEAT_ZERO_LONG, 1), (8, 0), (REPEAT_PREVIOUS, 3), (8, 0), (3, 0), (REPEAT_ZERO, 0)]);
+    }
+
+    #[test]
+    fn test_rejects_corrupt_data() {
+        let corrupt = |compressed: &[u8]| matches!(inflate(compressed), Err(Error::Corrupt(_)));
+
+        // Reserved block type, and a stored length that does not match its complement
+        assert!(corrupt(&[0b111]));
+        assert!(corrupt(&[0b001, 0x05, 0x00, 0x00, 0x00]));
+
+        let words = vector("words.dynamic.deflate");
+        assert!(corrupt(&words[..words.len() / 2]));
+        assert!(corrupt(&[]));
+
+        // A match before anything has been decoded
+        let (literals, distances) = fixed_codes();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = BitWriter::with_order(&mut compressed, BitOrder::LsbFirst);
+            writer.write_bits_le(1, 1).unwrap();
+            writer.write_bits_le(FIXED, 2).unwrap();
+            literals.write_symbol(END_OF_BLOCK + 1, &mut writer).unwrap();
+            distances.write_symbol(0, &mut writer).unwrap();
+            literals.write_symbol(END_OF_BLOCK, &mut writer).unwrap();
+            writer.flush().unwrap();
+        }
+        assert!(corrupt(&compressed));
+
+        let mut output = Vec::new();
+        let err = Deflate::read_input_limited(&mut &vector("green.stored.deflate")[..], &mut output, 100).unwrap_err();
+        assert!(matches!(err, Error::Corrupt(_)), "{:?}", err);
+    }
+}
@@ -0,0 +1,119 @@
+//! The error type returned throughout the crate
+
+use std::fmt::{self, Display};
+use std::io;
+
+use crate::checksum::ChecksumError;
+
+/// Everything that can go wrong while compressing or decompressing
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing failed
+    Io(io::Error),
+    /// The data is damaged or cut short
+    Corrupt(String),
+    /// The data uses a format, version or feature this build cannot decode
+    Unsupported(String),
+    /// The decoded data does not match the checksum stored with it
+    ChecksumMismatch(ChecksumError),
+    /// An argument is out of range or the options cannot be used together
+    InvalidParams(String),
+}
+
+/// A `Result` with the crate's [`Error`]
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Returns the closest `io::ErrorKind`, used when the error has to
+    /// travel through a `Read` or `Write` implementation
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Error::Io(err) => err.kind(),
+            Error::Corrupt(_) | Error::ChecksumMismatch(_) => io::ErrorKind::InvalidData,
+            Error::Unsupported(_) => io::ErrorKind::Unsupported,
+            Error::InvalidParams(_) => io::ErrorKind::InvalidInput,
+        }
+    }
+
+    /// Returns true if the input ended before it was expected to
+    pub fn is_eof(&self) -> bool {
+        matches!(self, Error::Io(err) if err.kind() == io::ErrorKind::UnexpectedEof)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => err.fmt(f),
+            Error::Corrupt(msg) | Error::Unsupported(msg) | Error::InvalidParams(msg) => f.write_str(msg),
+            Error::ChecksumMismatch(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::ChecksumMismatch(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// An `Error` that went through a `Read` or `Write` implementation comes back out as itself
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        match err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            true => *err.into_inner().unwrap().downcast::<Error>().unwrap(),
+            false => Error::Io(err),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::Io(err) => err,
+            err => io::Error::new(err.kind(), err),
+        }
+    }
+}
+
+impl From<ChecksumError> for Error {
+    fn from(err: ChecksumError) -> Error {
+        Error::ChecksumMismatch(err)
+    }
+}
+
+// Data that ends early is damaged rather than unreadable
+pub(crate) fn truncated(err: Error) -> Error {
+    match err.is_eof() {
+        true => Error::Corrupt("Data is corrupt: it ends early".to_string()),
+        false => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_round_trip() {
+        let err = io::Error::from(Error::Corrupt("bad".to_string()));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(Error::from(err), Error::Corrupt(msg) if msg == "bad"));
+
+        let err = Error::from(io::Error::new(io::ErrorKind::NotFound, "missing"));
+        assert!(matches!(&err, Error::Io(err) if err.kind() == io::ErrorKind::NotFound));
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let eof = Error::from(io::Error::from(io::ErrorKind::UnexpectedEof));
+        assert!(eof.is_eof());
+        assert!(matches!(truncated(eof), Error::Corrupt(_)));
+        assert!(matches!(truncated(Error::InvalidParams("x".to_string())), Error::InvalidParams(_)));
+    }
+}
@@ -0,0 +1,81 @@
+//! Dumping Huffman trees and code tables for inspection
+
+use std::fmt::Write;
+
+use crate::error::{Error, Result};
+use crate::map::ByteMap;
+use crate::tree::{HuffmanTree, NodeData};
+
+/// Renders a huffman tree as a Graphviz DOT digraph
+///
+/// Leaves show their byte and frequency, internal nodes their frequency
+/// and every edge is labelled with the bit it adds to the code
+pub fn tree_to_dot(tree: &HuffmanTree) -> String {
+    let mut dot = String::from("digraph huffman {\n    node [shape=circle];\n");
+
+    for (idx, node) in tree.nodes().iter().enumerate() {
+        match *node {
+            NodeData::Leaf { byte, freq } => {
+                writeln!(dot, "    n{} [shape=box, label=\"{}\\n{}\"];", idx, byte_label(byte), freq).unwrap();
+            },
+            NodeData::Internal { left, right, freq } => {
+                writeln!(dot, "    n{} [label=\"{}\"];", idx, freq).unwrap();
+                writeln!(dot, "    n{} -> n{} [label=\"0\"];", idx, left).unwrap();
+                writeln!(dot, "    n{} -> n{} [label=\"1\"];", idx, right).unwrap();
+            },
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Dumps a code table as JSON, one entry per byte with `null` for
+/// bytes that have no code
+pub fn codes_to_json(table: &ByteMap<String>) -> Result<String> {
+    // Writing to a string cannot fail, only a table serde cannot represent can
+    serde_json::to_string_pretty(table)
+        .map_err(|e| Error::InvalidParams(format!("Code table cannot be written as JSON: {}", e)))
+}
+
+// Printable ASCII is shown quoted, everything else as hex
+fn byte_label(byte: u8) -> String {
+    match byte {
+        b'"' => "'\\\"'".to_string(),
+        b'\\' => "'\\\\'".to_string(),
+        b' ' => "' '".to_string(),
+        _ if byte.is_ascii_graphic() => format!("'{}'", byte as char),
+        _ => format!("0x{:02X}", byte),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::CountMap;
+
+    #[test]
+    fn test_tree_to_dot() {
+        let counts: CountMap = vec![(b'a', 3), (b'\n', 1)].into_iter().collect();
+        let dot = tree_to_dot(&HuffmanTree::from_counts(&counts));
+
+        assert!(dot.starts_with("digraph huffman {"));
+        assert!(dot.contains("[shape=box, label=\"0x0A\\n1\"];"));
+        assert!(dot.contains("[shape=box, label=\"'a'\\n3\"];"));
+        assert!(dot.contains("n2 [label=\"4\"];"));
+        assert!(dot.contains("n2 -> n0 [label=\"0\"];"));
+        assert!(dot.contains("n2 -> n1 [label=\"1\"];"));
+    }
+
+    #[test]
+    fn test_codes_to_json() {
+        let counts: CountMap = vec![(b'a', 3), (b'b', 1)].into_iter().collect();
+        let json = codes_to_json(&HuffmanTree::from_counts(&counts).codes()).unwrap();
+        let table: ByteMap<String> = serde_json::from_str(&json).unwrap();
+
+        assert!(json.contains("\"map\""));
+        assert_eq!(table.get(b'a').as_deref(), Some("1"));
+        assert_eq!(table.get(b'b').as_deref(), Some("0"));
+        assert_eq!(table.iter_nonzero().count(), 2);
+    }
+}
@@ -1,22 +1,287 @@
-use std::io::Error;
-use std::path::Path;
-use std::fs::{File, OpenOptions};
-
-/// Creates a file with read and write access
-/// 
-/// Returns [`Result<File, Error>`] if the file already exists
-pub fn create_file(path: &Path) -> Result<File, Error> {
-    let file = OpenOptions::new()
-        .write(true)
-        .read(true)
-        .create(true)
-        .open(path);
-
-    match file {
-        Ok(file) => Ok(file),
-        Err(e) => {
-            println!("File {} already exists! Please try a different path", path.display());
-            Err(e)
+//! Writing output files atomically
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::error::{Error, Result};
+
+// Keeps temporary names unique between threads of one process
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// What to do when an output file already exists
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputPolicy {
+    /// Fail with an [`Error::Io`] of kind [`ErrorKind::AlreadyExists`]
+    #[default]
+    FailIfExists,
+    /// Replace the existing file
+    Overwrite,
+    /// Write to the first free name of the form `name.1.ext`, `name.2.ext`, ...
+    Rename,
+}
+
+/// An output file that is written under a temporary name in the same
+/// directory and only moved into place by [`AtomicFile::commit`]
+///
+/// Until then the final path is untouched, so a half-written output never
+/// shows up there. The temporary file is removed if the `AtomicFile` is
+/// dropped without being committed
+pub struct AtomicFile {
+    file: File,
+    temp: PathBuf,
+    target: PathBuf,
+    policy: OutputPolicy,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Returns the path the file will have once committed
+    pub fn path(&self) -> &Path {
+        &self.target
+    }
+
+    /// Returns the open temporary file
+    pub fn as_file(&self) -> &File {
+        &self.file
+    }
+
+    /// Returns the temporary path the data is written to
+    pub fn temp_path(&self) -> &Path {
+        &self.temp
+    }
+
+    /// Flushes the data to disk and moves the file into place
+    ///
+    /// Returns the final path, which only differs from the one asked for
+    /// with [`OutputPolicy::Rename`]
+    pub fn commit(mut self) -> Result<PathBuf> {
+        self.file.flush()?;
+        self.file.sync_all()?;
+
+        let target = match self.policy {
+            OutputPolicy::Overwrite => {
+                fs::rename(&self.temp, &self.target)?;
+                self.target.clone()
+            },
+            OutputPolicy::FailIfExists => {
+                link_new(&self.temp, &self.target)?;
+                self.target.clone()
+            },
+            // Someone may have taken the name since it was picked
+            OutputPolicy::Rename => loop {
+                let target = free_name(&self.target)?;
+                match link_new(&self.temp, &target) {
+                    Ok(()) => break target,
+                    Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            },
+        };
+        self.committed = true;
+
+        sync_dir(&target)?;
+        Ok(target)
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Read for AtomicFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Seek for AtomicFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+/// Starts writing an output file at `path`
+///
+/// With [`OutputPolicy::FailIfExists`] an existing file is reported
+/// straight away, so no work is wasted on an output that cannot be kept
+pub fn create(path: &Path, policy: OutputPolicy) -> Result<AtomicFile> {
+    if policy == OutputPolicy::FailIfExists && path.exists() {
+        return Err(already_exists(path).into());
+    }
+
+    let name = path.file_name()
+        .ok_or_else(|| Error::InvalidParams(format!("{} is not a file path", path.display())))?;
+
+    loop {
+        let mut temp_name = OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(".{}.{}.tmp", std::process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+        let temp = path.with_file_name(temp_name);
+
+        match OpenOptions::new().read(true).write(true).create_new(true).open(&temp) {
+            Ok(file) => {
+                return Ok(AtomicFile {
+                    file,
+                    temp,
+                    target: path.to_path_buf(),
+                    policy,
+                    committed: false,
+                });
+            },
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(io::Error::new(e.kind(), format!("Cannot create {}: {}", temp.display(), e)).into()),
+        }
+    }
+}
+
+// Moves `from` to `to` unless `to` exists. A hard link fails instead of
+// replacing, unlike a rename. Filesystems without hard links fall back
+// to a check followed by a rename
+fn link_new(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::hard_link(from, to) {
+        Ok(()) => fs::remove_file(from),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Err(already_exists(to)),
+        Err(_) if to.exists() => Err(already_exists(to)),
+        Err(_) => fs::rename(from, to),
+    }
+}
+
+fn already_exists(path: &Path) -> io::Error {
+    io::Error::new(ErrorKind::AlreadyExists, format!("{} already exists", path.display()))
+}
+
+// Returns the first `name.N.ext` next to `path` that does not exist yet
+fn free_name(path: &Path) -> io::Result<PathBuf> {
+    if !path.exists() {
+        return Ok(path.to_path_buf());
+    }
+
+    let stem = path.file_stem().unwrap_or_default();
+    for n in 1..u32::MAX {
+        let mut name = stem.to_os_string();
+        name.push(format!(".{}", n));
+        if let Some(ext) = path.extension() {
+            name.push(".");
+            name.push(ext);
+        }
+
+        let candidate = path.with_file_name(name);
+        if !candidate.exists() {
+            return Ok(candidate);
         }
     }
-}
\ No newline at end of file
+    Err(io::Error::new(ErrorKind::AlreadyExists, format!("No free name for {}", path.display())))
+}
+
+// Makes the rename itself durable
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Writes all of `data` to `path` atomically
+pub fn write(path: &Path, data: &[u8], policy: OutputPolicy) -> Result<PathBuf> {
+    let mut file = create(path, policy)?;
+    file.write_all(data)?;
+    file.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("file_compressor_fileops_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_fail_if_exists() {
+        let dir = scratch("fail");
+        let path = dir.join("out.txt");
+
+        write(&path, b"first", OutputPolicy::FailIfExists).unwrap();
+        let err = write(&path, b"second", OutputPolicy::FailIfExists).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+
+        // Someone else creates the file while ours is being written
+        let other = dir.join("race.txt");
+        let mut file = create(&other, OutputPolicy::FailIfExists).unwrap();
+        file.write_all(b"ours").unwrap();
+        fs::write(&other, b"theirs").unwrap();
+        assert_eq!(file.commit().unwrap_err().kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&other).unwrap(), b"theirs");
+
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_overwrite_truncates() {
+        let dir = scratch("overwrite");
+        let path = dir.join("out.txt");
+
+        write(&path, b"a much longer first version", OutputPolicy::Overwrite).unwrap();
+        write(&path, b"short", OutputPolicy::Overwrite).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"short");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rename() {
+        let dir = scratch("rename");
+        let path = dir.join("out.txt");
+
+        assert_eq!(write(&path, b"0", OutputPolicy::Rename).unwrap(), path);
+        assert_eq!(write(&path, b"1", OutputPolicy::Rename).unwrap(), dir.join("out.1.txt"));
+        assert_eq!(write(&path, b"2", OutputPolicy::Rename).unwrap(), dir.join("out.2.txt"));
+        assert_eq!(fs::read(dir.join("out.1.txt")).unwrap(), b"1");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_nothing_appears_until_commit() {
+        let dir = scratch("uncommitted");
+        let path = dir.join("out.txt");
+
+        let mut file = create(&path, OutputPolicy::Overwrite).unwrap();
+        file.write_all(b"half").unwrap();
+        assert!(!path.exists());
+        assert!(file.temp_path().exists());
+        drop(file);
+
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,306 @@
+//! The file formats compressed data can be written in, told apart by their first bytes
+
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::container::{self, Options};
+use crate::error::Result;
+use crate::fileops::{self, OutputPolicy};
+use crate::gzip::{self, GzipHeader};
+use crate::metadata::{Metadata, Restore};
+use crate::zlib;
+
+/// A kind of compressed file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// This crate's own [`container`], which any algorithm can go in
+    Fcmp,
+    /// [`gzip`] members, readable by gzip and zcat
+    Gzip,
+    /// A [`zlib`] stream
+    Zlib,
+}
+
+impl Format {
+    /// Every format, checked in this order when detecting
+    pub const ALL: [Format; 3] = [Format::Fcmp, Format::Gzip, Format::Zlib];
+
+    /// Returns the name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Fcmp => "fcmp",
+            Format::Gzip => "gzip",
+            Format::Zlib => "zlib",
+        }
+    }
+
+    /// Returns the extension of files in this format
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Format::Fcmp => "fcmp",
+            Format::Gzip => "gz",
+            Format::Zlib => "zz",
+        }
+    }
+
+    /// Returns the format whose magic bytes start `bytes`, if any
+    pub fn detect(bytes: &[u8]) -> Option<Format> {
+        Format::ALL.into_iter().find(|format| match format {
+            Format::Fcmp => bytes.starts_with(&container::MAGIC),
+            Format::Gzip => bytes.starts_with(&gzip::MAGIC),
+            Format::Zlib => zlib::is_header(bytes),
+        })
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What [`decompress`] found
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decoded {
+    pub format: Format,
+    /// The algorithm or pipeline of a container, otherwise the format's name
+    pub codec: String,
+    pub size: u64,
+}
+
+/// Decodes a container, gzip file or zlib stream, whichever the reader holds
+///
+/// Anything else fails like a container with bad magic bytes
+pub fn decompress<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> Result<Decoded> {
+    let format = Format::detect(reader.fill_buf()?).unwrap_or(Format::Fcmp);
+    let (codec, size) = match format {
+        Format::Fcmp => {
+            let header = container::decompress(reader, writer)?;
+            (header.codec(), header.original_size)
+        },
+        Format::Gzip => (format.to_string(), gzip::decompress(reader, writer)?.1),
+        Format::Zlib => (format.to_string(), zlib::decompress(reader, writer)?),
+    };
+    Ok(Decoded { format, codec, size })
+}
+
+/// What [`describe`] found in a compressed file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Summary {
+    pub format: Format,
+    /// The algorithm or pipeline of a container, otherwise the format's name
+    pub codec: String,
+    pub size: u64,
+    /// The stored file name
+    pub name: Option<String>,
+    /// The stored modification time in seconds since the Unix epoch
+    pub mtime: Option<i64>,
+}
+
+/// Reads what a compressed file in any format says about its contents
+///
+/// A container's header has everything. A gzip file has a name and
+/// modification time in its header and the size in its trailer, see
+/// [`gzip::trailer_size`]. A zlib stream stores nothing, so it is decoded
+/// to count its size
+pub fn describe(path: &Path) -> Result<Summary> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let format = Format::detect(reader.fill_buf()?).unwrap_or(Format::Fcmp);
+
+    match format {
+        Format::Fcmp => {
+            let header = container::Header::read_from(&mut reader)?;
+            let metadata = header.metadata.as_ref();
+            Ok(Summary {
+                format,
+                codec: header.codec(),
+                size: header.original_size,
+                name: metadata.map(|metadata| metadata.name.clone()).filter(|name| !name.is_empty()),
+                mtime: metadata.map(|metadata| metadata.mtime),
+            })
+        },
+        Format::Gzip => {
+            let header = GzipHeader::read_from(&mut reader)?;
+            Ok(Summary {
+                format,
+                codec: format.to_string(),
+                size: gzip::trailer_size(&mut reader)? as u64,
+                name: header.name,
+                mtime: Some(header.mtime as i64).filter(|&mtime| mtime != 0),
+            })
+        },
+        Format::Zlib => Ok(Summary {
+            format,
+            codec: format.to_string(),
+            size: zlib::decompress(&mut reader, &mut io::sink())?,
+            name: None,
+            mtime: None,
+        }),
+    }
+}
+
+/// Compresses a file into `format`, at the level in `options`
+///
+/// Containers keep all of the file's [`Metadata`], gzip keeps its name
+/// and modification time and zlib keeps nothing. An existing `output` is
+/// handled according to `policy`. Returns the path that was written
+pub fn compress_file(input: &Path, output: &Path, format: Format, options: &Options, policy: OutputPolicy) -> Result<PathBuf> {
+    if format == Format::Fcmp {
+        return Ok(container::compress_file(input, output, options, policy)?.1);
+    }
+
+    let metadata = Metadata::from_path(input)?;
+    let mut reader = BufReader::new(File::open(input)?);
+    let mut file = fileops::create(output, policy)?;
+    {
+        let mut writer = BufWriter::new(&mut file);
+        match format {
+            Format::Gzip => gzip::compress(&mut reader, &mut writer, &GzipHeader::from(&metadata), options.level)?,
+            _ => zlib::compress(&mut reader, &mut writer, options.level)?,
+        }
+        writer.flush()?;
+    }
+    file.commit()
+}
+
+/// Decompresses a file in any format, like [`container::decompress_file`]
+///
+/// A gzip file's stored name and modification time are put back if
+/// `restore` asks for them. A zlib stream is written under the input's
+/// name without its extension
+pub fn decompress_file(input: &Path, output: Option<&Path>, restore: &Restore, policy: OutputPolicy) -> Result<PathBuf> {
+    let mut reader = BufReader::new(File::open(input)?);
+    let format = Format::detect(reader.fill_buf()?).unwrap_or(Format::Fcmp);
+
+    let header = match format {
+        Format::Fcmp => return container::decompress_file(input, output, restore, policy),
+        Format::Gzip => GzipHeader::read_from(&mut reader)?,
+        Format::Zlib => GzipHeader::default(),
+    };
+    let name = header.name.as_deref().filter(|_| restore.name);
+    let mut file = fileops::create(&container::output_path(input, output, name)?, policy)?;
+    {
+        let mut writer = BufWriter::new(&mut file);
+        match format {
+            Format::Gzip => gzip::decode_members(&mut reader, &mut writer)?,
+            _ => zlib::decompress(&mut reader, &mut writer)?,
+        };
+        writer.flush()?;
+    }
+
+    if restore.mtime && header.mtime != 0 {
+        let metadata = Metadata { mtime: header.mtime as i64, ..Metadata::default() };
+        metadata.restore(file.temp_path(), &Restore { mtime: true, ..Restore::none() })?;
+    }
+    file.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::container::Algorithm;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("file_compressor_format_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Format::detect(b"FCMP\x01"), Some(Format::Fcmp));
+        assert_eq!(Format::detect(&[0x1f, 0x8b, 8]), Some(Format::Gzip));
+        assert_eq!(Format::detect(&[0x78, 0x9c]), Some(Format::Zlib));
+        assert_eq!(Format::detect(b"plain text"), None);
+        assert_eq!(Format::detect(b""), None);
+    }
+
+    #[test]
+    fn test_decompress_detects_format() {
+        let data = b"so much depends upon a red wheel barrow glazed with rain water";
+        for format in Format::ALL {
+            let mut compressed = Vec::new();
+            match format {
+                Format::Fcmp => drop(container::compress(&mut &data[..], &mut compressed, Algorithm::Lzh).unwrap()),
+                Format::Gzip => gzip::compress(&mut &data[..], &mut compressed, &GzipHeader::default(), 9).unwrap(),
+                Format::Zlib => zlib::compress(&mut &data[..], &mut compressed, 9).unwrap(),
+            }
+
+            let mut output = Vec::new();
+            let decoded = decompress(&mut &compressed[..], &mut output).unwrap();
+            assert_eq!(output, data);
+            assert_eq!((decoded.format, decoded.size), (format, data.len() as u64));
+        }
+
+        let err = decompress(&mut &b"plain text"[..], &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("bad magic"), "{}", err);
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let dir = scratch("files");
+        let input = dir.join("poem.txt");
+        fs::write(&input, b"the red wheel barrow").unwrap();
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        File::options().write(true).open(&input).unwrap().set_modified(mtime).unwrap();
+
+        let options = Options::new(Algorithm::Lzss);
+        for format in Format::ALL {
+            let compressed = dir.join(format!("poem.txt.{}", format.suffix()));
+            compress_file(&input, &compressed, format, &options, OutputPolicy::FailIfExists).unwrap();
+
+            // Renamed so only a stored name leads back to poem.txt
+            let renamed = dir.join(format!("renamed.{}", format.suffix()));
+            fs::rename(&compressed, &renamed).unwrap();
+            let output = decompress_file(&renamed, None, &Restore::all(), OutputPolicy::Overwrite).unwrap();
+
+            let expected = match format {
+                Format::Zlib => dir.join("renamed"),
+                _ => input.clone(),
+            };
+            assert_eq!(output, expected, "{}", format);
+            assert_eq!(fs::read(&output).unwrap(), b"the red wheel barrow");
+            if format != Format::Zlib {
+                assert_eq!(fs::metadata(&output).unwrap().modified().unwrap(), mtime, "{}", format);
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_describe() {
+        let dir = scratch("describe");
+        let input = dir.join("poem.txt");
+        fs::write(&input, b"so much depends upon a red wheel barrow").unwrap();
+        File::options().write(true).open(&input).unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(1_600_000_000)).unwrap();
+
+        let options = Options::new(Algorithm::Lzh);
+        for format in Format::ALL {
+            let compressed = dir.join(format!("poem.txt.{}", format.suffix()));
+            compress_file(&input, &compressed, format, &options, OutputPolicy::FailIfExists).unwrap();
+
+            let summary = describe(&compressed).unwrap();
+            assert_eq!((summary.format, summary.size), (format, 39), "{}", format);
+            let (name, mtime) = match format {
+                Format::Zlib => (None, None),
+                _ => (Some("poem.txt".to_string()), Some(1_600_000_000)),
+            };
+            assert_eq!((summary.name, summary.mtime), (name, mtime), "{}", format);
+        }
+        assert_eq!(describe(&dir.join("poem.txt.fcmp")).unwrap().codec, "Lzh");
+
+        // Too short to hold a gzip trailer
+        let short = dir.join("short.gz");
+        fs::write(&short, [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3]).unwrap();
+        assert!(matches!(describe(&short), Err(crate::error::Error::Corrupt(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,296 @@
+//! gzip files (RFC 1952): DEFLATE data in one or more members, each with a
+//! header holding the file name and modification time and a CRC32 trailer
+
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
+
+use crate::checksum::{ChecksumError, ChecksumReader, ChecksumWriter};
+use crate::deflate::Deflate;
+use crate::error::{truncated, Error, Result};
+use crate::lzss;
+use crate::metadata::Metadata;
+
+/// The first two bytes of every member
+pub const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// The only compression method, DEFLATE
+const METHOD_DEFLATE: u8 = 8;
+
+const FLAG_TEXT: u8 = 0b0000_0001;
+const FLAG_HEADER_CRC: u8 = 0b0000_0010;
+const FLAG_EXTRA: u8 = 0b0000_0100;
+const FLAG_NAME: u8 = 0b0000_1000;
+const FLAG_COMMENT: u8 = 0b0001_0000;
+const KNOWN_FLAGS: u8 = FLAG_TEXT | FLAG_HEADER_CRC | FLAG_EXTRA | FLAG_NAME | FLAG_COMMENT;
+
+// XFL values for the slowest and fastest settings, and the OS field
+const EXTRA_FLAGS_BEST: u8 = 2;
+const EXTRA_FLAGS_FASTEST: u8 = 4;
+const OS: u8 = if cfg!(unix) { 3 } else { 255 };
+
+/// What a member header says about the file it came from
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GzipHeader {
+    /// FNAME, the file name without any directories
+    pub name: Option<String>,
+    /// MTIME in seconds since the Unix epoch, zero if unknown
+    pub mtime: u32,
+}
+
+impl From<&Metadata> for GzipHeader {
+    fn from(metadata: &Metadata) -> GzipHeader {
+        GzipHeader {
+            name: Some(metadata.name.clone()).filter(|name| !name.is_empty()),
+            // Times gzip cannot hold are left unknown
+            mtime: u32::try_from(metadata.mtime).unwrap_or(0),
+        }
+    }
+}
+
+impl GzipHeader {
+    /// Writes a member header for data compressed at `level`
+    pub fn write_to<W: Write>(&self, writer: &mut W, level: u8) -> Result<()> {
+        let flags = match self.name {
+            Some(_) => FLAG_NAME,
+            None => 0,
+        };
+        let extra_flags = match level {
+            lzss::MAX_LEVEL => EXTRA_FLAGS_BEST,
+            lzss::MIN_LEVEL => EXTRA_FLAGS_FASTEST,
+            _ => 0,
+        };
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[METHOD_DEFLATE, flags])?;
+        writer.write_all(&self.mtime.to_le_bytes())?;
+        writer.write_all(&[extra_flags, OS])?;
+        if let Some(name) = &self.name {
+            if name.contains('\0') {
+                return Err(Error::InvalidParams("A gzip file name cannot contain a NUL byte".to_string()));
+            }
+            writer.write_all(name.as_bytes())?;
+            writer.write_all(&[0])?;
+        }
+        Ok(())
+    }
+
+    /// Reads a member header, skipping the extra field and comment
+    ///
+    /// Fails if the magic bytes are wrong, the method is not DEFLATE,
+    /// reserved flags are set or the header CRC does not match
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<GzipHeader> {
+        let mut reader = ChecksumReader::new(reader);
+        let [id1, id2, method, flags, mtime @ .., _extra_flags, _os]: [u8; 10] = read_array(&mut reader)?;
+
+        if [id1, id2] != MAGIC {
+            return Err(Error::Corrupt("Not a gzip file (bad magic bytes)".to_string()));
+        }
+        if method != METHOD_DEFLATE {
+            return Err(Error::Unsupported(format!("Unknown gzip compression method {}", method)));
+        }
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(Error::Unsupported(format!("Unknown gzip flags {:#010b}", flags & !KNOWN_FLAGS)));
+        }
+
+        if flags & FLAG_EXTRA != 0 {
+            let len = u16::from_le_bytes(read_array(&mut reader)?);
+            let skipped = std::io::copy(&mut (&mut reader).take(len as u64), &mut std::io::sink())?;
+            if skipped < len as u64 {
+                return Err(Error::Corrupt("Data is corrupt: gzip extra field ends early".to_string()));
+            }
+        }
+        let name = match flags & FLAG_NAME {
+            0 => None,
+            _ => Some(latin1_or_utf8(read_zero_terminated(&mut reader)?)),
+        };
+        if flags & FLAG_COMMENT != 0 {
+            read_zero_terminated(&mut reader)?;
+        }
+        if flags & FLAG_HEADER_CRC != 0 {
+            let actual = reader.checksum() & 0xFFFF;
+            let expected = u16::from_le_bytes(read_array(&mut reader)?) as u32;
+            if expected != actual {
+                return Err(ChecksumError { block: None, expected, actual }.into());
+            }
+        }
+
+        Ok(GzipHeader { name, mtime: u32::from_le_bytes(mtime) })
+    }
+}
+
+/// Compresses everything in the reader into a single gzip member
+///
+/// `level` goes from [`lzss::MIN_LEVEL`] to [`lzss::MAX_LEVEL`] like the
+/// other codecs built on LZSS
+pub fn compress<R: Read, W: Write>(reader: &mut R, writer: &mut W, header: &GzipHeader, level: u8) -> Result<()> {
+    header.write_to(writer, level)?;
+
+    let mut reader = ChecksumReader::new(reader);
+    Deflate::write_output_level(&mut reader, writer, level)?;
+    let size = reader.read_bytes();
+
+    writer.write_all(&reader.finish().to_le_bytes())?;
+    writer.write_all(&(size as u32).to_le_bytes())?;
+    Ok(())
+}
+
+/// Decodes every member of a gzip file, one after another
+///
+/// Returns the header of the first member and the total decoded size
+pub fn decompress<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> Result<(GzipHeader, u64)> {
+    let header = GzipHeader::read_from(reader)?;
+    let size = decode_members(reader, writer)?;
+    Ok((header, size))
+}
+
+/// Decodes the data of a member whose header has already been read, and
+/// every member after it, returning the total decoded size
+///
+/// Fails with [`Error::ChecksumMismatch`] if a member does not match its
+/// CRC32, after its data has been written
+pub fn decode_members<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> Result<u64> {
+    let mut total = 0;
+    loop {
+        let mut output = ChecksumWriter::new(&mut *writer, None);
+        Deflate::read_input(reader, &mut output)?;
+        output.flush()?;
+        let size = output.written();
+        let (actual, _) = output.finish();
+
+        let expected = u32::from_le_bytes(read_array(reader)?);
+        if expected != actual {
+            return Err(ChecksumError { block: None, expected, actual }.into());
+        }
+        let expected_size = u32::from_le_bytes(read_array(reader)?);
+        if expected_size != size as u32 {
+            return Err(Error::Corrupt(format!(
+                "Data is corrupt: decoded {} bytes but the trailer says {}", size, expected_size
+            )));
+        }
+        total += size;
+
+        if reader.fill_buf()?.is_empty() {
+            return Ok(total);
+        }
+        GzipHeader::read_from(reader)?;
+    }
+}
+
+/// Returns the ISIZE of the last member's trailer, the size of its data
+/// modulo 2^32, without decoding anything
+///
+/// For a file with a single member under 4 GB this is the original size,
+/// which is what `gzip -l` shows
+pub fn trailer_size<R: Read + Seek>(reader: &mut R) -> Result<u32> {
+    // A member is at least a 10 byte header, an empty block and the trailer
+    if reader.seek(SeekFrom::End(0))? < 18 {
+        return Err(Error::Corrupt("Data is corrupt: it ends early".to_string()));
+    }
+    reader.seek(SeekFrom::End(-4))?;
+    Ok(u32::from_le_bytes(read_array(reader)?))
+}
+
+// Everything read here is part of the format, so running out is corruption
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes).map_err(|e| truncated(e.into()))?;
+    Ok(bytes)
+}
+
+fn read_zero_terminated<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    loop {
+        match read_array(reader)? {
+            [0] => return Ok(bytes),
+            [byte] => bytes.push(byte),
+        }
+    }
+}
+
+// The RFC says ISO 8859-1, but gzip writes names as they are on disk,
+// which these days is mostly UTF-8
+fn latin1_or_utf8(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes).unwrap_or_else(|e| e.into_bytes().into_iter().map(char::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    fn vector(name: &str) -> Vec<u8> {
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/deflate").join(name)).unwrap()
+    }
+
+    fn green() -> Vec<u8> {
+        std::fs::read(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("green.txt")).unwrap()
+    }
+
+    fn gunzip(compressed: &[u8]) -> Result<(GzipHeader, Vec<u8>)> {
+        let mut output = Vec::new();
+        let (header, size) = decompress(&mut &compressed[..], &mut output)?;
+        assert_eq!(size, output.len() as u64);
+        Ok((header, output))
+    }
+
+    #[test]
+    fn test_gzip_vectors() {
+        let (header, output) = gunzip(&vector("green.txt.gz")).unwrap();
+        assert_eq!(header, GzipHeader { name: Some("green.txt".to_string()), mtime: 1700000000 });
+        assert_eq!(output, green());
+
+        // Two members, the second with a comment, an extra field and a header CRC
+        let (header, output) = gunzip(&vector("members.gz")).unwrap();
+        assert_eq!(header.name, None);
+        assert_eq!(output, [&green()[..], &vector("words.txt")[..]].concat());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = green();
+        let header = GzipHeader { name: Some("grün.txt".to_string()), mtime: 1234567890 };
+
+        let mut compressed = Vec::new();
+        compress(&mut &data[..], &mut compressed, &header, lzss::MAX_LEVEL).unwrap();
+        assert_eq!(compressed[..2], MAGIC);
+        assert_eq!(gunzip(&compressed).unwrap(), (header, data.clone()));
+
+        // Members written separately read back as one file
+        let mut second = Vec::new();
+        compress(&mut &b""[..], &mut second, &GzipHeader::default(), lzss::MIN_LEVEL).unwrap();
+        compressed.extend_from_slice(&second);
+        compressed.extend_from_slice(&compressed.clone());
+        assert_eq!(gunzip(&compressed).unwrap().1, [&data[..], &data[..]].concat());
+    }
+
+    #[test]
+    fn test_latin1_name() {
+        assert_eq!(latin1_or_utf8(b"caf\xe9".to_vec()), "café");
+        assert_eq!(latin1_or_utf8("café".as_bytes().to_vec()), "café");
+    }
+
+    #[test]
+    fn test_rejects_bad_members() {
+        let compressed = vector("green.txt.gz");
+
+        let mut bad_crc = compressed.clone();
+        let trailer = bad_crc.len() - 8;
+        bad_crc[trailer] ^= 1;
+        assert!(matches!(gunzip(&bad_crc), Err(Error::ChecksumMismatch(_))));
+
+        let mut bad_size = compressed.clone();
+        *bad_size.last_mut().unwrap() ^= 1;
+        assert!(matches!(gunzip(&bad_size), Err(Error::Corrupt(_))));
+
+        let mut bad_method = compressed.clone();
+        bad_method[2] = 7;
+        assert!(matches!(gunzip(&bad_method), Err(Error::Unsupported(_))));
+
+        let mut trailing = compressed.clone();
+        trailing.extend_from_slice(b"junk");
+        assert!(matches!(gunzip(&trailing), Err(Error::Corrupt(_))));
+
+        assert!(matches!(gunzip(&compressed[..compressed.len() - 3]), Err(Error::Corrupt(_))));
+        assert!(matches!(gunzip(b"FCMP"), Err(Error::Corrupt(_))));
+    }
+}
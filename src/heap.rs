@@ -1,21 +1,74 @@
-pub struct Heap<T: PartialOrd> {
+//! A binary heap with a pluggable ordering
+
+use std::cmp::Ordering;
+use std::iter::FromIterator;
+
+/// Decides the priority of two items in a [`Heap`]
+///
+/// The item that compares [`Ordering::Greater`] is removed first
+pub trait Compare<T> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+/// Removes the largest item first
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Max;
+
+/// Removes the smallest item first
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Min;
+
+impl<T: Ord> Compare<T> for Max {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+impl<T: Ord> Compare<T> for Min {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        b.cmp(a)
+    }
+}
+
+impl<T, F> Compare<T> for F
+    where F: Fn(&T, &T) -> Ordering
+{
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
+
+pub struct Heap<T, C = Max> {
     items: Vec<T>,
+    cmp: C,
 }
 
-impl<T: PartialOrd> Heap<T> {
-    /// Instantiates a new `Heap`
+impl<T: Ord> Heap<T, Max> {
+    /// Instantiates a new max `Heap`
     pub fn new(items: Vec<T>) -> Self {
-        let mut heap = Self {items};
+        Heap::with_comparator(items, Max)
+    }
+}
+
+impl<T: Ord> Heap<T, Min> {
+    /// Instantiates a new min `Heap`
+    pub fn new_min(items: Vec<T>) -> Self {
+        Heap::with_comparator(items, Min)
+    }
+}
+
+impl<T, C: Compare<T>> Heap<T, C> {
+    /// Instantiates a new `Heap` ordered by `cmp`
+    pub fn with_comparator(items: Vec<T>, cmp: C) -> Self {
+        let mut heap = Self {items, cmp};
         heap.heapify();
         heap
     }
 
     fn heapify(&mut self) {
-        if self.size() != 0 {
-            for i in (0..=(self.size()/2 - 1)).rev() {
-                self.sift_down(i)
-            }
-        } 
+        for i in (0..self.size()/2).rev() {
+            self.sift_down(i)
+        }
     }
 
     /// Insert an item into the heap
@@ -37,18 +90,70 @@ impl<T: PartialOrd> Heap<T> {
         }
      }
 
+    /// Returns the item that would be removed next
+    pub fn peek(&self) -> Option<&T> {
+        self.items.first()
+    }
+
+    /// Inserts an item and then removes the highest priority item
+    ///
+    /// Faster than calling `insert` followed by `remove`
+    pub fn push_pop(&mut self, item: T) -> T {
+        match self.items.first() {
+            Some(top) if self.higher(top, &item) => {
+                let top = std::mem::replace(&mut self.items[0], item);
+                self.sift_down(0);
+                top
+            },
+            _ => item,
+        }
+    }
+
+    /// Consumes the heap and returns its items from lowest to highest priority
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let mut end = self.size();
+        while end > 1 {
+            end -= 1;
+            self.items.swap(0, end);
+            self.sift_down_until(0, end);
+        }
+        self.items
+    }
+
+    /// Consumes the heap and returns its items in heap order
+    ///
+    /// Keeps the allocation, so the `Vec` can be reused for a new heap
+    pub fn into_vec(self) -> Vec<T> {
+        self.items
+    }
+
+    /// Removes every item from the heap in priority order
+    pub fn drain(&mut self) -> Drain<'_, T, C> {
+        Drain { heap: self }
+    }
+
+    // Returns true if `a` has a higher priority than `b`
+    fn higher(&self, a: &T, b: &T) -> bool {
+        self.cmp.compare(a, b) == Ordering::Greater
+    }
+
     // Sifts an item down the heap until the priority is correct
-    fn sift_down(&mut self, mut pos: usize) {
-        while pos < self.size() / 2 {
+    fn sift_down(&mut self, pos: usize) {
+        self.sift_down_until(pos, self.size());
+    }
+
+    // Sifts an item down the first `end` items of the heap
+    fn sift_down_until(&mut self, mut pos: usize, end: usize) {
+        while pos < end / 2 {
             let left = 2 * pos + 1;
             let right = left + 1;
             let mut largest = pos;
 
-            if self.items[left] > self.items[largest] {
+            if self.higher(&self.items[left], &self.items[largest]) {
                 largest = left;
             }
 
-            if right < self.size() && self.items[right] > self.items[largest] {
+            if right < end && self.higher(&self.items[right], &self.items[largest]) {
                 largest = right;
             }
 
@@ -66,7 +171,7 @@ impl<T: PartialOrd> Heap<T> {
         let mut pos = self.size() - 1;
         while pos > 0 {
             let parent = (pos - 1) / 2;
-            if self.items[pos] <= self.items[parent] {
+            if !self.higher(&self.items[pos], &self.items[parent]) {
                 return;
             }
             self.items.swap(pos, parent);
@@ -78,8 +183,46 @@ impl<T: PartialOrd> Heap<T> {
     pub fn size(&self) -> usize {
         self.items.len()
     }
+
+    /// Returns true if the heap has no items
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
  }
 
+impl<T, C: Compare<T> + Default> FromIterator<T> for Heap<T, C> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Heap::with_comparator(iter.into_iter().collect(), C::default())
+    }
+}
+
+impl<T, C: Compare<T>> Extend<T> for Heap<T, C> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+/// Draining iterator returned by [`Heap::drain`]
+pub struct Drain<'a, T, C: Compare<T>> {
+    heap: &'a mut Heap<T, C>,
+}
+
+impl<'a, T, C: Compare<T>> Iterator for Drain<'a, T, C> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.heap.remove()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.size(), Some(self.heap.size()))
+    }
+}
+
+impl<'a, T, C: Compare<T>> ExactSizeIterator for Drain<'a, T, C> {}
+
  #[test]
  fn test_1() {
     let mut heap = Heap::new(vec![4,7,2,3,4]);
@@ -96,4 +239,44 @@ impl<T: PartialOrd> Heap<T> {
     assert_eq!(heap.remove(), None);
  }
 
- 
+ #[test]
+ fn test_min() {
+    let mut heap = Heap::new_min(vec![4,7,2,3,4]);
+    assert_eq!(heap.peek(), Some(&2));
+    assert_eq!(heap.drain().collect::<Vec<_>>(), vec![2,3,4,4,7]);
+    assert!(heap.is_empty());
+ }
+
+ #[test]
+ fn test_comparator() {
+    let mut heap = Heap::with_comparator(vec!["ccc", "a", "bb"], |a: &&str, b: &&str| a.len().cmp(&b.len()));
+    assert_eq!(heap.remove(), Some("ccc"));
+    assert_eq!(heap.remove(), Some("bb"));
+    assert_eq!(heap.remove(), Some("a"));
+ }
+
+ #[test]
+ fn test_push_pop() {
+    let mut heap = Heap::new(vec![5, 1, 3]);
+    assert_eq!(heap.push_pop(9), 9);
+    assert_eq!(heap.push_pop(2), 5);
+    assert_eq!(heap.into_sorted_vec(), vec![1,2,3]);
+
+    let mut empty: Heap<i32, Min> = Heap::new_min(vec![]);
+    assert_eq!(empty.push_pop(4), 4);
+ }
+
+ #[test]
+ fn test_collect_and_extend() {
+    let mut heap: Heap<i32, Min> = vec![8, 6, 9].into_iter().collect();
+    heap.extend(vec![1, 7]);
+    assert_eq!(heap.size(), 5);
+    assert_eq!(heap.into_sorted_vec(), vec![9,8,7,6,1]);
+ }
+
+ #[test]
+ fn test_single() {
+    let mut heap = Heap::new(vec![1]);
+    assert_eq!(heap.remove(), Some(1));
+    assert_eq!(heap.remove(), None);
+ }
@@ -0,0 +1,210 @@
+//! Counting how often each byte occurs, on one or several threads
+
+use std::io::Read;
+use std::ops::Range;
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use crate::error::Result;
+use crate::lzss::fill_buffer;
+use crate::map::CountMap;
+
+const READ_SIZE: usize = 4 << 20;
+const BUFFER_COUNT: usize = 4;
+const TABLES: usize = 4;
+
+/// Byte counts spread over several tables so that runs of the same byte
+/// increment different memory locations instead of waiting on each other
+pub struct Histogram {
+    tables: Box<[[usize; 256]; TABLES]>,
+}
+
+impl Histogram {
+    /// Instantiates an empty `Histogram`
+    pub fn new() -> Histogram {
+        Histogram {
+            tables: Box::new([[0; 256]; TABLES]),
+        }
+    }
+
+    /// Counts every byte of `data`
+    #[inline]
+    pub fn add(&mut self, data: &[u8]) {
+        let [t0, t1, t2, t3] = &mut *self.tables;
+        let mut chunks = data.chunks_exact(TABLES);
+        for chunk in &mut chunks {
+            t0[chunk[0] as usize] += 1;
+            t1[chunk[1] as usize] += 1;
+            t2[chunk[2] as usize] += 1;
+            t3[chunk[3] as usize] += 1;
+        }
+        for byte in chunks.remainder() {
+            t0[*byte as usize] += 1;
+        }
+    }
+
+    /// Sums the tables into a `CountMap`
+    pub fn into_counts(self) -> CountMap {
+        let mut map = CountMap::new();
+        for table in self.tables.iter() {
+            for (byte, count) in table.iter().enumerate() {
+                map.increment_count_by(byte as u8, *count);
+            }
+        }
+        map
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Counts a slice that is already in memory
+pub fn count_slice(data: &[u8]) -> CountMap {
+    let mut histogram = Histogram::new();
+    histogram.add(data);
+    histogram.into_counts()
+}
+
+/// Counts every byte of a reader using `workers` threads
+///
+/// The input is read in large blocks that are split between a pool of
+/// workers which lives for the whole call. Each worker keeps its own
+/// histogram and the results are merged once at the end. Blocks are
+/// recycled, so memory use stays at a few blocks however large the input is
+pub fn count_reader<R: Read>(reader: R, workers: usize) -> Result<CountMap> {
+    count_reader_with(reader, workers, READ_SIZE)
+}
+
+fn count_reader_with<R: Read>(mut reader: R, workers: usize, read_size: usize) -> Result<CountMap> {
+    let mut buffer = vec![0u8; read_size];
+    let len = fill_buffer(&mut reader, &mut buffer)?;
+
+    // Not worth starting threads for
+    if workers <= 1 || len < read_size {
+        let mut histogram = Histogram::new();
+        let mut len = len;
+        while len > 0 {
+            histogram.add(&buffer[..len]);
+            len = fill_buffer(&mut reader, &mut buffer)?;
+        }
+        return Ok(histogram.into_counts());
+    }
+
+    thread::scope(|s| {
+        let (free_tx, free_rx) = mpsc::channel::<Vec<u8>>();
+        let mut senders = Vec::with_capacity(workers);
+        let mut handles = Vec::with_capacity(workers);
+
+        for _ in 0..workers {
+            let (tx, rx) = mpsc::channel::<(Arc<Vec<u8>>, Range<usize>)>();
+            let free_tx = free_tx.clone();
+            handles.push(s.spawn(move || {
+                let mut histogram = Histogram::new();
+                for (block, range) in rx {
+                    histogram.add(&block[range]);
+                    // Whoever lets go of a block last hands it back
+                    if let Some(block) = Arc::into_inner(block) {
+                        let _ = free_tx.send(block);
+                    }
+                }
+                histogram.into_counts()
+            }));
+            senders.push(tx);
+        }
+        drop(free_tx);
+
+        let mut allocated = 1;
+        let mut spare: Vec<Vec<u8>> = Vec::new();
+        let mut len = len;
+
+        let result = loop {
+            let block = Arc::new(buffer);
+            let part = len.div_ceil(workers);
+            for (i, tx) in senders.iter().enumerate() {
+                let start = (i * part).min(len);
+                let end = (start + part).min(len);
+                if start < end {
+                    tx.send((Arc::clone(&block), start..end)).unwrap();
+                }
+            }
+            if let Some(block) = Arc::into_inner(block) {
+                spare.push(block);
+            }
+
+            buffer = match spare.pop() {
+                Some(block) => block,
+                None if allocated < BUFFER_COUNT => {
+                    allocated += 1;
+                    vec![0u8; read_size]
+                },
+                None => free_rx.recv().unwrap(),
+            };
+
+            match fill_buffer(&mut reader, &mut buffer) {
+                Ok(0) => break Ok(()),
+                Ok(n) => len = n,
+                Err(e) => break Err(e),
+            }
+        };
+        drop(senders);
+
+        let mut counts = CountMap::new();
+        for handle in handles {
+            counts += handle.join().unwrap();
+        }
+        result.map(|_| counts)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive(data: &[u8]) -> [usize; 256] {
+        let mut counts = [0; 256];
+        for byte in data {
+            counts[*byte as usize] += 1;
+        }
+        counts
+    }
+
+    fn sample(len: usize) -> Vec<u8> {
+        let mut state = 0x2545F491u32;
+        (0..len)
+            .map(|i| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                // Mix in long runs to exercise the interleaved tables
+                if i % 1000 < 300 { b'a' } else { state as u8 }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_count_slice() {
+        for len in [0, 1, 3, 4, 5, 1023] {
+            let data = sample(len);
+            assert_eq!(count_slice(&data).get_copy(), naive(&data));
+        }
+    }
+
+    #[test]
+    fn test_count_reader_single_thread() {
+        let data = sample(10_000);
+        let counts = count_reader_with(&data[..], 1, 1024).unwrap();
+        assert_eq!(counts.get_copy(), naive(&data));
+    }
+
+    #[test]
+    fn test_count_reader_pool() {
+        let data = sample(100_003);
+        for workers in [2, 3, 8] {
+            let counts = count_reader_with(&data[..], workers, 1024).unwrap();
+            assert_eq!(counts.get_copy(), naive(&data));
+        }
+    }
+}
@@ -1,182 +1,194 @@
-use std::collections::HashMap;
+//! Huffman coding, which gives frequent bytes shorter codes
+
 use std::fs::File;
-use std::hash::BuildHasher;
-use std::io::{self, Read, BufWriter, BufReader, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::num::NonZeroUsize;
 use std::path::Path;
-use std::thread::{self, available_parallelism};
-
-use crate::fileops;
+use std::thread::available_parallelism;
+
+use crate::bitreader::BitReader;
+use crate::bitwriter::BitWriter;
+use crate::codec::{Codec, Encoder};
+use crate::error::{truncated, Result};
+use crate::fileops::{self, OutputPolicy};
+use crate::histogram;
 use crate::map::{ByteMap, CountMap};
-use crate::heap::Heap;
-use crate::node::Node;
-
-use concat_strs::concat_strs;
-use itertools::Itertools;
-
-const HASH_MAP_CAPACITY: usize = 256;
-const BYTES_MULTIPLIER: usize = 80_000;
-const READER_CAPACITY: usize = 10_485_760;      // Probably can't fit in stack
+use crate::tree::HuffmanTree;
 
 pub struct Huffman;
 
 impl Huffman {
-    // Builds a huffman tree from a heap of nodes
-    fn build_tree(heap: &mut Heap<Node>) -> Node {
-        let size = heap.size();
-
-        for _ in 1..size {
-            let left = heap.remove().unwrap();
-            let right = heap.remove().unwrap();
-            let freq = left.get_freq() + right.get_freq();
-
-            let node = Node::new_huffman(left, right, freq);
-            heap.insert(node);
+    /// Writes the codes of every byte of a file to a new file, without
+    /// the tree needed to decode them
+    pub fn encode(file_path: &Path, new_path: &Path) -> Result<()> {
+        let counts = Self::create_freq_table(&File::open(file_path)?)?;
+        let table = HuffmanTree::from_counts(&counts).codes();
+
+        let mut reader = BufReader::new(File::open(file_path)?);
+        let mut new_file = fileops::create(new_path, OutputPolicy::Overwrite)?;
+        {
+            let mut writer = BitWriter::new(BufWriter::new(&mut new_file));
+            loop {
+                let buffer = reader.fill_buf()?;
+                if buffer.is_empty() {
+                    break;
+                }
+                Self::write_codes(&table, buffer.iter().copied(), &mut writer)?;
+                let len = buffer.len();
+                reader.consume(len);
+            }
+            writer.flush()?;
         }
-
-        heap.remove().unwrap()
+        new_file.commit()?;
+        Ok(())
     }
 
-    pub fn encode(file_path: &Path, new_path: &Path) {
-        let file = File::open(file_path).unwrap();
-        let new_file = fileops::create_file(new_path).unwrap();
+    /// Writes the huffman tree of `data` followed by its encoded bytes,
+    /// so that it can be read back with [`Huffman::decode_data`]
+    pub fn encode_data<W: Write>(data: &[u8], writer: &mut W) -> Result<()> {
+        let tree = HuffmanTree::from_counts(&count(data));
+        let mut writer = BitWriter::new(writer);
 
-        let mut reader = BufReader::new(&file).bytes();
-        let mut writer = BufWriter::new(&new_file);
+        tree.write_to(&mut writer)?;
+        Self::write_codes(&tree.codes(), data.iter().copied(), &mut writer)?;
+        writer.flush()
+    }
 
-        let mut heap = Huffman::create_node_heap(file_path);
-        let root = Huffman::build_tree(&mut heap);
-        let table = Self::generate_codes(&root);
+    /// Reads a huffman tree and then decodes `len` bytes with it
+    ///
+    /// Only the bytes that make up the encoded data are consumed
+    pub fn decode_data<R: Read, W: Write>(reader: &mut R, writer: &mut W, len: u64) -> Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
 
-        while let Some(Ok(byte)) = reader.next() {
-            let mut bit_num = 7;
-            let mut output: u8 = 0b0000_0000;
+        let mut reader = BitReader::new(reader);
+        let tree = HuffmanTree::read_from(&mut reader)?;
+        for _ in 0..len {
+            writer.write_all(&[tree.decode_byte(&mut reader)?])?;
+        }
+
+        Ok(())
+    }
 
+    // Writes the code of every byte to the writer
+    pub(crate) fn write_codes<I, W>(table: &ByteMap<String>, bytes: I, writer: &mut BitWriter<W>) -> Result<()>
+        where I: Iterator<Item = u8>, W: Write
+    {
+        for byte in bytes {
             if let Some(code) = table.get(byte) {
                 for bit in code.chars() {
-                    match bit {
-                        '0' => {
-                            output |= (0 << bit_num);
-                        },
-                        '1' => {
-                            output |= (1 << bit_num);
-                        },
-                        _ => (),
-                    }
-                    if bit_num > 0 { 
-                        bit_num -= 1; 
-                    } else if bit_num == 0 { 
-                        writer.write_all(&[output]);
-                        output = 0b0000_0000; 
-                    }
+                    writer.write_bit(bit == '1')?;
                 }
             };
         }
-    }
 
-    // Generate huffman codes recursively from a huffman tree
-    fn generate_codes(root: &Node) -> ByteMap<String> {
-        let mut map = ByteMap::new();
-        Self::gen_codes_helper(&mut map, Some(root), "");
-        map
+        Ok(())
     }
 
-    // Helper for generating huffman codes from a huffman tree
-    fn gen_codes_helper(table: &mut ByteMap<String>, curr_node: Option<&Node>, curr_code: &str) {
-        if let Some(node) = curr_node {
-            if node.get_left() == None && node.get_right() == None {
-                table.insert(curr_code.to_string(), node.get_byte().unwrap());
-            } else {
-                Self::gen_codes_helper(table, node.get_left(), &concat_strs!(curr_code, '0'));
-                Self::gen_codes_helper(table, node.get_right(), &concat_strs!(curr_code, '1'));
-            }
-        }
+    /// Counts how often every byte occurs in a file
+    pub fn create_freq_table(file: &File) -> Result<CountMap> {
+        let worker_count = available_parallelism().map_or(1, NonZeroUsize::get);
+        histogram::count_reader(file, worker_count)
     }
+}
 
-    // Does the same thing as generate_codes but uses a stack instead of recursion
-    // to prevent stack overflow
-    fn gen_codes__safe(root: &Node) -> ByteMap<String> {
-        let mut stack = Vec::with_capacity(256);
-        let mut table = ByteMap::new();
-        stack.push((root, String::new()));
-
-        while let Some((node, code)) = stack.pop() {
-            let code: &str = code.as_ref();
-            if node.get_left() == None && node.get_right() == None {
-                table.insert(code.to_string(), node.get_byte().unwrap());
-            } else {
-                if let Some(left_node) = node.get_left() {
-                    stack.push((left_node, concat_strs!(code, '0')));
-                } 
-                if let Some(right_node) = node.get_right() {
-                    stack.push((right_node, concat_strs!(code, '1')));
-                }
-            }
-        }
-        table
-    }
+impl Codec for Huffman {
+    type Encoder<W: Write> = HuffmanEncoder<W>;
+    type Decoder<R: Read> = HuffmanDecoder<R>;
 
-    // Creates a heap of nodes from a file
-    fn create_node_heap(file_path: &Path) -> Heap<Node> {
-        let file = File::open(file_path).unwrap();
-        let char_count = Self::create_freq_table(&file);
+    fn encoder<W: Write>(&self, writer: W) -> HuffmanEncoder<W> {
+        HuffmanEncoder { writer, data: Vec::new() }
+    }
 
-        let mut node_vec: Vec<Node> = Vec::with_capacity(256);
-        for (byte, count) in char_count {
-            node_vec.push(Node::new(byte, count));
+    fn decoder<R: Read>(&self, reader: R) -> HuffmanDecoder<R> {
+        HuffmanDecoder {
+            reader: BitReader::new(reader),
+            tree: None,
+            remaining: 0,
         }
-        Heap::new(node_vec)
-    } 
+    }
+}
 
-    pub fn create_freq_table(file: &File) -> CountMap {
-        let worker_count = available_parallelism().unwrap().get();
-        let bytes_each = worker_count * BYTES_MULTIPLIER;
+/// Compresses everything written to it with Huffman coding
+///
+/// The tree depends on every byte, so the data is held in memory and only
+/// written by [`Encoder::finish`] as its length (8 bytes BE) followed by
+/// the output of [`Huffman::encode_data`]
+pub struct HuffmanEncoder<W: Write> {
+    writer: W,
+    data: Vec<u8>,
+}
 
-        let reader = io::BufReader::with_capacity(READER_CAPACITY, file);
-        let mut result = CountMap::new();
+impl<W: Write> Write for HuffmanEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
 
-        for page in &reader.bytes().chunks(bytes_each) {
-            let data: Vec<_> = page.filter_map(Result::ok).collect();
+    // Nothing can be written before the last byte is known
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
 
-            match data.len() {
-                0 => (),
-                l if l < 320_000 => count_single_thread(&mut result, data),
-                _ => count_multi_thread(&mut result, data, worker_count)
-            }
+impl<W: Write> Encoder<W> for HuffmanEncoder<W> {
+    fn finish(mut self) -> Result<W> {
+        self.writer.write_all(&(self.data.len() as u64).to_be_bytes())?;
+        if !self.data.is_empty() {
+            Huffman::encode_data(&self.data, &mut self.writer)?;
         }
-
-        result
+        self.writer.flush()?;
+        Ok(self.writer)
     }
+}
 
+/// Decompresses data written by [`HuffmanEncoder`]
+pub struct HuffmanDecoder<R: Read> {
+    reader: BitReader<R>,
+    // Read along with the length on the first call to `read`
+    tree: Option<HuffmanTree>,
+    remaining: u64,
 }
 
-#[inline]
-fn count_single_thread(result: &mut CountMap, data: Vec<u8>) {
-    for byte in data {
-        result.increment_count(byte);
+impl<R: Read> HuffmanDecoder<R> {
+    // Reads the length and the tree in front of the codes
+    fn start(&mut self) -> Result<()> {
+        let mut len = 0u64;
+        for _ in 0..8 {
+            len = len << 8 | self.reader.read_byte().map_err(truncated)? as u64;
+        }
+        self.remaining = len;
+        self.tree = Some(match len {
+            0 => HuffmanTree::new(),
+            _ => HuffmanTree::read_from(&mut self.reader)?,
+        });
+        Ok(())
     }
 }
 
-#[inline]
-fn count_multi_thread(result: &mut CountMap, data: Vec<u8>, threads: usize) {
-    thread::scope(|s| {
-        let mut handles = Vec::with_capacity(threads);
-        for chunk in data.chunks(data.len()/ threads + 1) {
-            handles.push(s.spawn( || count(chunk)));
+impl<R: Read> Read for HuffmanDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
         }
-        for handle in handles {
-            let map = handle.join().unwrap();
-            for (key, value) in map {
-                result.increment_count_by(key, value);
-            }
+        if self.tree.is_none() {
+            self.start()?;
         }
-    })
+
+        let Some(tree) = &self.tree else {
+            return Ok(0);
+        };
+        let len = buf.len().min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        for byte in &mut buf[..len] {
+            *byte = tree.decode_byte(&mut self.reader)?;
+        }
+        self.remaining -= len as u64;
+        Ok(len)
+    }
 }
 
 #[inline]
-fn count(input: &[u8]) -> CountMap {
-    let mut map = CountMap::new();
-    for byte in input {
-        map.increment_count(*byte);
-    }
-    map
-}
\ No newline at end of file
+pub(crate) fn count(input: &[u8]) -> CountMap {
+    histogram::count_slice(input)
+}
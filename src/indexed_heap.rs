@@ -0,0 +1,300 @@
+//! A heap whose items can be changed or removed through handles
+
+use std::cmp::Ordering;
+
+use crate::heap::{Compare, Max, Min};
+
+/// Refers to an item inside an [`IndexedHeap`]
+///
+/// A handle stops being valid once its item is removed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: usize,
+    generation: u32,
+}
+
+struct Slot<T> {
+    item: Option<T>,
+    pos: usize,
+    generation: u32,
+}
+
+/// A heap that keeps track of where each item is so that its
+/// priority can be changed or it can be removed in O(log n)
+pub struct IndexedHeap<T, C = Max> {
+    order: Vec<usize>,
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    cmp: C,
+}
+
+impl<T: Ord> IndexedHeap<T, Max> {
+    /// Instantiates a new max `IndexedHeap`
+    pub fn new() -> Self {
+        IndexedHeap::with_comparator(Max)
+    }
+}
+
+impl<T: Ord> IndexedHeap<T, Min> {
+    /// Instantiates a new min `IndexedHeap`
+    pub fn new_min() -> Self {
+        IndexedHeap::with_comparator(Min)
+    }
+}
+
+impl<T, C: Compare<T>> IndexedHeap<T, C> {
+    /// Instantiates a new `IndexedHeap` ordered by `cmp`
+    pub fn with_comparator(cmp: C) -> Self {
+        IndexedHeap {
+            order: Vec::new(),
+            slots: Vec::new(),
+            free: Vec::new(),
+            cmp,
+        }
+    }
+
+    /// Inserts an item and returns a handle to it
+    pub fn insert(&mut self, item: T) -> Handle {
+        let pos = self.order.len();
+        let index = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.item = Some(item);
+                slot.pos = pos;
+                index
+            },
+            None => {
+                self.slots.push(Slot { item: Some(item), pos, generation: 0 });
+                self.slots.len() - 1
+            }
+        };
+        self.order.push(index);
+        self.sift_up(pos);
+
+        Handle { index, generation: self.slots[index].generation }
+    }
+
+    /// Returns the highest priority item and its handle
+    pub fn peek(&self) -> Option<(Handle, &T)> {
+        let index = *self.order.first()?;
+        Some((self.handle(index), self.item(index)))
+    }
+
+    /// Removes the highest priority item
+    pub fn pop(&mut self) -> Option<(Handle, T)> {
+        let index = *self.order.first()?;
+        let handle = self.handle(index);
+        self.remove(handle).map(|item| (handle, item))
+    }
+
+    /// Returns the item behind a handle
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.slot(handle)?.item.as_ref()
+    }
+
+    /// Returns true if the handle still refers to an item
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.get(handle).is_some()
+    }
+
+    /// Replaces the item behind a handle and moves it to its new place
+    ///
+    /// Returns the old item, or `None` if the handle is no longer valid
+    pub fn change_priority(&mut self, handle: Handle, new: T) -> Option<T> {
+        let pos = self.slot(handle)?.pos;
+        let old = self.slots[handle.index].item.replace(new)?;
+        self.restore(pos);
+        Some(old)
+    }
+
+    /// Removes the item behind a handle
+    ///
+    /// Returns `None` if the handle is no longer valid
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        let pos = self.slot(handle)?.pos;
+        let last = self.order.len() - 1;
+
+        self.swap(pos, last);
+        self.order.pop();
+        if pos < last {
+            self.restore(pos);
+        }
+
+        let slot = &mut self.slots[handle.index];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        slot.item.take()
+    }
+
+    /// Returns the size of the heap
+    pub fn size(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Returns true if the heap has no items
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    fn slot(&self, handle: Handle) -> Option<&Slot<T>> {
+        self.slots.get(handle.index)
+            .filter(|slot| slot.generation == handle.generation && slot.item.is_some())
+    }
+
+    fn handle(&self, index: usize) -> Handle {
+        Handle { index, generation: self.slots[index].generation }
+    }
+
+    fn item(&self, index: usize) -> &T {
+        self.slots[index].item.as_ref().unwrap()
+    }
+
+    // Returns true if the item at heap position `a` has a higher priority than `b`
+    fn higher(&self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.item(self.order[a]), self.item(self.order[b]));
+        self.cmp.compare(a, b) == Ordering::Greater
+    }
+
+    // Swaps two heap positions and updates the slots that point at them
+    fn swap(&mut self, a: usize, b: usize) {
+        self.order.swap(a, b);
+        self.slots[self.order[a]].pos = a;
+        self.slots[self.order[b]].pos = b;
+    }
+
+    // Moves the item at `pos` up or down after its priority changed
+    fn restore(&mut self, pos: usize) {
+        if pos > 0 && self.higher(pos, (pos - 1) / 2) {
+            self.sift_up(pos);
+        } else {
+            self.sift_down(pos);
+        }
+    }
+
+    // Sifts an item up the heap until the priority is correct
+    fn sift_up(&mut self, mut pos: usize) {
+        while pos > 0 {
+            let parent = (pos - 1) / 2;
+            if !self.higher(pos, parent) {
+                return;
+            }
+            self.swap(pos, parent);
+            pos = parent;
+        }
+    }
+
+    // Sifts an item down the heap until the priority is correct
+    fn sift_down(&mut self, mut pos: usize) {
+        while pos < self.size() / 2 {
+            let left = 2 * pos + 1;
+            let right = left + 1;
+            let mut largest = pos;
+
+            if self.higher(left, largest) {
+                largest = left;
+            }
+
+            if right < self.size() && self.higher(right, largest) {
+                largest = right;
+            }
+
+            if largest == pos {
+                return;
+            }
+
+            self.swap(pos, largest);
+            pos = largest;
+        }
+    }
+}
+
+impl<T: Ord> Default for IndexedHeap<T, Max> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Small deterministic generator so the model test needs no extra crates
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: u64) -> u64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 33) % bound
+        }
+    }
+
+    #[test]
+    fn test_change_priority() {
+        let mut heap = IndexedHeap::new_min();
+        let a = heap.insert(5);
+        let b = heap.insert(3);
+        let c = heap.insert(8);
+
+        assert_eq!(heap.peek(), Some((b, &3)));
+        assert_eq!(heap.change_priority(c, 1), Some(8));
+        assert_eq!(heap.peek(), Some((c, &1)));
+        assert_eq!(heap.change_priority(c, 9), Some(1));
+
+        assert_eq!(heap.pop(), Some((b, 3)));
+        assert_eq!(heap.pop(), Some((a, 5)));
+        assert_eq!(heap.pop(), Some((c, 9)));
+        assert_eq!(heap.pop(), None);
+    }
+
+    #[test]
+    fn test_stale_handle() {
+        let mut heap = IndexedHeap::new();
+        let a = heap.insert(1);
+        assert_eq!(heap.remove(a), Some(1));
+
+        let b = heap.insert(2);
+        assert!(!heap.contains(a));
+        assert_eq!(heap.remove(a), None);
+        assert_eq!(heap.change_priority(a, 7), None);
+        assert_eq!(heap.get(b), Some(&2));
+    }
+
+    #[test]
+    fn test_against_model() {
+        let mut rng = Lcg(0x5eed);
+        let mut heap = IndexedHeap::new();
+        let mut model: Vec<(Handle, u64)> = Vec::new();
+
+        for _ in 0..10_000 {
+            match rng.next(5) {
+                0 | 1 => {
+                    let item = rng.next(1000);
+                    model.push((heap.insert(item), item));
+                },
+                2 if !model.is_empty() => {
+                    let i = rng.next(model.len() as u64) as usize;
+                    let item = rng.next(1000);
+                    assert_eq!(heap.change_priority(model[i].0, item), Some(model[i].1));
+                    model[i].1 = item;
+                },
+                3 if !model.is_empty() => {
+                    let i = rng.next(model.len() as u64) as usize;
+                    let (handle, item) = model.swap_remove(i);
+                    assert_eq!(heap.remove(handle), Some(item));
+                },
+                _ => {
+                    let max = model.iter().map(|(_, item)| *item).max();
+                    match heap.pop() {
+                        Some((handle, item)) => {
+                            assert_eq!(Some(item), max);
+                            let i = model.iter().position(|(h, _)| *h == handle).unwrap();
+                            assert_eq!(model.swap_remove(i).1, item);
+                        },
+                        None => assert!(model.is_empty()),
+                    }
+                },
+            }
+            assert_eq!(heap.size(), model.len());
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! LZSS and Huffman compression with the bit I/O and file formats they are built on
+//!
+//! - [`lzss::LZSS`], [`huffman::Huffman`], [`lzh::Lzh`] and [`deflate::Deflate`] are the codecs, all streaming through the [`codec::Codec`] trait
+//! - [`lzh::Lzh`] codes LZSS tokens with [`canonical`] huffman codes, like DEFLATE
+//! - [`deflate::Deflate`] reads and writes raw DEFLATE (RFC 1951), the format of zlib, gzip and zip
+//! - [`gzip`] and [`zlib`] wrap DEFLATE for standard tools, and [`format`](mod@format) tells them and containers apart
+//! - [`bitwriter::BitWriter`] and [`bitreader::BitReader`] read and write single bits
+//! - [`pipeline::Pipeline`] chains transforms and codecs, like delta then LZSS then Huffman
+//! - [`container`] wraps compressed data in a header with its algorithm, size and checksums
+//! - [`archive`] and [`seekable`] hold many files or independently compressed frames
+//! - [`map`], [`tree`], [`histogram`] and [`heap`] are the building blocks of Huffman coding
+//!
+//! Everything that can fail returns the crate's [`Error`].
+//!
+//! Compressing a buffer and getting it back:
+//!
+//! ```
+//! use file_compressor::container::{self, Algorithm};
+//!
+//! let data = b"so much depends upon a red wheel barrow glazed with rain water";
+//! let mut compressed = Vec::new();
+//! container::compress(&mut &data[..], &mut compressed, Algorithm::Lzss).unwrap();
+//!
+//! let mut output = Vec::new();
+//! container::decompress(&mut &compressed[..], &mut output).unwrap();
+//! assert_eq!(output, data);
+//! ```
+
+pub mod archive;
+pub mod bitreader;
+pub mod bitwriter;
+pub mod canonical;
+pub mod checksum;
+pub mod codec;
+pub mod container;
+pub mod deflate;
+pub mod error;
+pub mod export;
+pub mod fileops;
+pub mod format;
+pub mod gzip;
+pub mod heap;
+pub mod histogram;
+pub mod huffman;
+pub mod indexed_heap;
+pub mod lzh;
+pub mod lzss;
+pub mod map;
+pub mod metadata;
+pub mod pipeline;
+pub mod seekable;
+pub mod tree;
+pub mod zlib;
+#[cfg(feature = "async")]
+pub mod asyncio;
+
+mod tests;
+
+pub use error::{Error, Result};
@@ -0,0 +1,437 @@
+//! LZSS tokens written with huffman codes, in the style of DEFLATE
+//!
+//! Plain LZSS spends 9 bits on every literal and 17 on every reference.
+//! Here each block of tokens gets two canonical huffman codes, one for
+//! literals and match lengths together and one for distances, so common
+//! bytes and short, near matches cost only a few bits
+
+use std::io::{self, Read, Write};
+
+use crate::bitreader::BitReader;
+use crate::bitwriter::BitWriter;
+use crate::canonical::{CanonicalCode, MAX_CODE_LENGTH};
+use crate::codec::{Codec, Encoder};
+use crate::error::{truncated, Error, Result};
+use crate::lzss::{self, Output, LZSS};
+
+// Tokens that share one pair of codes
+const BLOCK_TOKENS: usize = 1 << 14;
+
+// Furthest back a reference can reach
+const WINDOW: usize = 4095;
+
+// Literals are symbols 0 to 255, then the end of a block, then one per match length
+const END_OF_BLOCK: usize = 256;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 15;
+const LITERAL_SYMBOLS: usize = END_OF_BLOCK + 1 + MAX_MATCH - MIN_MATCH + 1;
+
+// Distances fall in ranges of a first distance and a number of extra bits, as in DEFLATE
+const DISTANCE_BASE: [u16; 24] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073,
+];
+const DISTANCE_EXTRA: [u8; 24] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10,
+];
+
+// Bits for the number of code lengths in a table, a code length, and a run of unused symbols
+const COUNT_BITS: u8 = 9;
+const LENGTH_BITS: u8 = 4;
+const RUN_BITS: u8 = 4;
+
+pub struct Lzh;
+
+impl Lzh {
+    /// Compresses everything in the reader, searching as far back as
+    /// `level` allows like [`LZSS::write_output_level`]
+    ///
+    /// Every block starts with a `1` bit and its code tables, and a `0`
+    /// bit ends the data, so it can be decoded without knowing its length
+    pub fn write_output_level<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        level: u8
+    ) -> Result<()> {
+        let mut writer = BitWriter::new(writer);
+        let mut buffer = vec![0u8; lzss::BUFFER_SIZE];
+
+        // Whole buffers are encoded, so the output does not depend on how
+        // the reader splits up its reads
+        loop {
+            let len = lzss::fill_buffer(reader, &mut buffer)?;
+            if len == 0 { break; }
+
+            write_blocks(&buffer[..len], &mut writer, level)?;
+        }
+
+        writer.write_bit(false)?;
+        writer.flush()
+    }
+
+    /// Decodes data written by [`Lzh::write_output_level`]
+    ///
+    /// Fails if it decodes to more than `limit` bytes. Only the bytes that
+    /// make up the encoded data are consumed
+    pub fn read_input_limited<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        limit: u64
+    ) -> Result<()> {
+        let mut reader = BitReader::new(reader);
+        let mut window = Vec::new();
+        let mut room = limit;
+
+        while reader.read_bit().map_err(truncated)? == 1 {
+            let start = window.len();
+            read_block(&mut reader, &mut window, room)?;
+            writer.write_all(&window[start..])?;
+            room -= (window.len() - start) as u64;
+
+            // Later blocks can only reach back one window
+            let old = window.len().saturating_sub(WINDOW);
+            window.drain(..old);
+        }
+
+        Ok(())
+    }
+}
+
+impl Codec for Lzh {
+    type Encoder<W: Write> = LzhEncoder<W>;
+    type Decoder<R: Read> = LzhDecoder<R>;
+
+    fn encoder<W: Write>(&self, writer: W) -> LzhEncoder<W> {
+        LzhEncoder {
+            writer: BitWriter::new(writer),
+            buffer: Vec::new(),
+        }
+    }
+
+    fn decoder<R: Read>(&self, reader: R) -> LzhDecoder<R> {
+        LzhDecoder {
+            reader: BitReader::new(reader),
+            window: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+}
+
+/// Compresses everything written to it with LZH
+///
+/// The output is the same as [`Lzh::write_output_level`] at the best level
+pub struct LzhEncoder<W: Write> {
+    writer: BitWriter<W>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> LzhEncoder<W> {
+    fn write_buffer(&mut self) -> Result<()> {
+        write_blocks(&self.buffer, &mut self.writer, lzss::MAX_LEVEL)?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for LzhEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(lzss::BUFFER_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == lzss::BUFFER_SIZE {
+            self.write_buffer()?;
+        }
+        Ok(len)
+    }
+
+    // Encodes what is buffered so far, which costs some compression as
+    // later data cannot refer back into it
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.write_buffer()?;
+        }
+        self.writer.get_mut().flush()
+    }
+}
+
+impl<W: Write> Encoder<W> for LzhEncoder<W> {
+    fn finish(mut self) -> Result<W> {
+        if !self.buffer.is_empty() {
+            self.write_buffer()?;
+        }
+        self.writer.write_bit(false)?;
+        self.writer.into_inner()
+    }
+}
+
+/// Decompresses data written by [`LzhEncoder`] or [`Lzh::write_output_level`]
+pub struct LzhDecoder<R: Read> {
+    reader: BitReader<R>,
+    // The last block decoded, after up to one window of what came before
+    window: Vec<u8>,
+    // Where the output not read yet starts in `window`
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> Read for LzhDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.window.len() {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            let old = self.window.len().saturating_sub(WINDOW);
+            self.window.drain(..old);
+            self.pos = self.window.len();
+
+            match self.reader.read_bit().map_err(truncated)? {
+                0 => self.done = true,
+                _ => read_block(&mut self.reader, &mut self.window, u64::MAX)?,
+            }
+        }
+
+        let len = buf.len().min(self.window.len() - self.pos);
+        buf[..len].copy_from_slice(&self.window[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+// Writes the tokens of one buffer as blocks, each after a `1` bit
+fn write_blocks<W: Write>(data: &[u8], writer: &mut BitWriter<W>, level: u8) -> Result<()> {
+    for tokens in LZSS::encode_level(data, level).chunks(BLOCK_TOKENS) {
+        writer.write_bit(true)?;
+        write_block(tokens, writer)?;
+    }
+    Ok(())
+}
+
+fn length_symbol(length: u8) -> usize {
+    END_OF_BLOCK + 1 + length as usize - MIN_MATCH
+}
+
+fn distance_symbol(offset: u16) -> usize {
+    DISTANCE_BASE.partition_point(|&base| base <= offset) - 1
+}
+
+fn write_block<W: Write>(tokens: &[Output], writer: &mut BitWriter<W>) -> Result<()> {
+    let mut literal_freqs = vec![0usize; LITERAL_SYMBOLS];
+    let mut distance_freqs = vec![0usize; DISTANCE_BASE.len()];
+    for token in tokens {
+        match *token {
+            Output::Literal(byte) => literal_freqs[byte as usize] += 1,
+            Output::Reference { offset, length } => {
+                literal_freqs[length_symbol(length)] += 1;
+                distance_freqs[distance_symbol(offset)] += 1;
+            },
+        }
+    }
+    literal_freqs[END_OF_BLOCK] = 1;
+
+    let literals = CanonicalCode::from_freqs(&literal_freqs, MAX_CODE_LENGTH);
+    let distances = CanonicalCode::from_freqs(&distance_freqs, MAX_CODE_LENGTH);
+    write_lengths(literals.lengths(), writer)?;
+    write_lengths(distances.lengths(), writer)?;
+
+    for token in tokens {
+        match *token {
+            Output::Literal(byte) => literals.write_symbol(byte as usize, writer)?,
+            Output::Reference { offset, length } => {
+                literals.write_symbol(length_symbol(length), writer)?;
+                let symbol = distance_symbol(offset);
+                distances.write_symbol(symbol, writer)?;
+                writer.write_bits((offset - DISTANCE_BASE[symbol]) as u32, DISTANCE_EXTRA[symbol])?;
+            },
+        }
+    }
+    literals.write_symbol(END_OF_BLOCK, writer)
+}
+
+// Decodes one block onto the end of `window`, which holds what came before
+fn read_block<R: Read>(reader: &mut BitReader<R>, window: &mut Vec<u8>, room: u64) -> Result<()> {
+    let literals = read_lengths(reader, LITERAL_SYMBOLS)?;
+    let distances = read_lengths(reader, DISTANCE_BASE.len())?;
+    let start = window.len();
+
+    loop {
+        match literals.read_symbol(reader)? {
+            END_OF_BLOCK => return Ok(()),
+            byte @ 0..END_OF_BLOCK => window.push(byte as u8),
+            symbol => {
+                let length = symbol - END_OF_BLOCK - 1 + MIN_MATCH;
+                let distance = distances.read_symbol(reader)?;
+                let extra = reader.read_bits(DISTANCE_EXTRA[distance]).map_err(truncated)?;
+                let offset = DISTANCE_BASE[distance] as usize + extra as usize;
+                if offset > window.len() {
+                    return Err(Error::Corrupt(format!(
+                        "Data is corrupt: reference to {} bytes at {} back with {} decoded", length, offset, window.len()
+                    )));
+                }
+                let from = window.len() - offset;
+                for i in from..from + length {
+                    window.push(window[i]);
+                }
+            },
+        }
+
+        if (window.len() - start) as u64 > room {
+            return Err(Error::Corrupt(format!("Data is corrupt: it holds more than {} bytes", room)));
+        }
+    }
+}
+
+// Writes how many symbols have a length, then each length. An unused
+// symbol starts a run of up to 16 that are all unused
+fn write_lengths<W: Write>(lengths: &[u8], writer: &mut BitWriter<W>) -> Result<()> {
+    let count = lengths.iter().rposition(|&len| len > 0).map_or(0, |last| last + 1);
+    writer.write_bits(count as u32, COUNT_BITS)?;
+
+    let mut i = 0;
+    while i < count {
+        writer.write_bits(lengths[i] as u32, LENGTH_BITS)?;
+        match lengths[i] {
+            0 => {
+                let run = lengths[i..count].iter()
+                    .take(1 << RUN_BITS)
+                    .take_while(|&&len| len == 0)
+                    .count();
+                writer.write_bits(run as u32 - 1, RUN_BITS)?;
+                i += run;
+            },
+            _ => i += 1,
+        }
+    }
+    Ok(())
+}
+
+fn read_lengths<R: Read>(reader: &mut BitReader<R>, symbols: usize) -> Result<CanonicalCode> {
+    let count = reader.read_bits(COUNT_BITS).map_err(truncated)? as usize;
+    if count > symbols {
+        return Err(Error::Corrupt(format!("Data is corrupt: {} code lengths for {} symbols", count, symbols)));
+    }
+
+    let mut lengths = vec![0u8; symbols];
+    let mut i = 0;
+    while i < count {
+        match reader.read_bits(LENGTH_BITS).map_err(truncated)? {
+            0 => i += reader.read_bits(RUN_BITS).map_err(truncated)? as usize + 1,
+            len => {
+                lengths[i] = len as u8;
+                i += 1;
+            },
+        }
+    }
+    if i > count {
+        return Err(Error::Corrupt("Data is corrupt: code lengths run past the table".to_string()));
+    }
+    CanonicalCode::from_lengths(&lengths)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn round_trip(data: &[u8], level: u8) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        Lzh::write_output_level(&mut &data[..], &mut compressed, level).unwrap();
+
+        let mut output = Vec::new();
+        Lzh::read_input_limited(&mut &compressed[..], &mut output, data.len() as u64).unwrap();
+        assert_eq!(output, data);
+        compressed
+    }
+
+    fn lzss_size(data: &[u8]) -> usize {
+        let mut compressed = Vec::new();
+        LZSS::write_output(&mut &data[..], &mut compressed).unwrap();
+        compressed.len()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for name in ["green.txt", "src/lzss.rs"] {
+            let data = std::fs::read(root.join(name)).unwrap();
+            for level in [lzss::MIN_LEVEL, lzss::MAX_LEVEL] {
+                round_trip(&data, level);
+            }
+        }
+        for data in [&b""[..], b"a", b"aaaaaaaaaaaaaaaaaaaaaaaaaaaa"] {
+            round_trip(data, lzss::MAX_LEVEL);
+        }
+    }
+
+    #[test]
+    fn test_beats_lzss_on_text() {
+        let data = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lzss.rs")).unwrap();
+        let compressed = round_trip(&data, lzss::MAX_LEVEL);
+        assert!(compressed.len() * 10 < lzss_size(&data) * 9, "{} vs {}", compressed.len(), lzss_size(&data));
+    }
+
+    #[test]
+    fn test_many_blocks() {
+        // Enough distinct tokens for several blocks, with matches across them
+        let data: Vec<u8> = (0..60_000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8 % 17 + b'a').collect();
+        round_trip(&data, lzss::MIN_LEVEL);
+    }
+
+    #[test]
+    fn test_encoder_matches_write_output() {
+        let data = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lzss.rs")).unwrap();
+        let mut expected = Vec::new();
+        Lzh::write_output_level(&mut &data[..], &mut expected, lzss::MAX_LEVEL).unwrap();
+
+        let mut encoder = Lzh.encoder(Vec::new());
+        for chunk in data.chunks(100) {
+            encoder.write_all(chunk).unwrap();
+        }
+        assert_eq!(encoder.finish().unwrap(), expected);
+
+        // A reader handing out 64 bytes at a time is encoded the same
+        let mut compressed = Vec::new();
+        let mut reader = ShortReader(&data);
+        Lzh::write_output_level(&mut reader, &mut compressed, lzss::MAX_LEVEL).unwrap();
+        assert_eq!(compressed, expected);
+
+        let mut reader = &expected[..];
+        let mut output = Vec::new();
+        Lzh.decoder(&mut reader).read_to_end(&mut output).unwrap();
+        assert_eq!(output, data);
+        assert!(reader.is_empty());
+    }
+
+    struct ShortReader<'a>(&'a [u8]);
+
+    impl Read for ShortReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(64);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_distance_symbols() {
+        for offset in 1..=WINDOW as u16 {
+            let symbol = distance_symbol(offset);
+            let extra = offset - DISTANCE_BASE[symbol];
+            assert!(extra < 1 << DISTANCE_EXTRA[symbol], "{}", offset);
+        }
+        assert_eq!(distance_symbol(4095), 23);
+    }
+
+    #[test]
+    fn test_rejects_corrupt_data() {
+        let data = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("green.txt")).unwrap();
+        let mut compressed = Vec::new();
+        Lzh::write_output_level(&mut &data[..], &mut compressed, lzss::MAX_LEVEL).unwrap();
+
+        let err = Lzh::read_input_limited(&mut &compressed[..compressed.len() / 2], &mut Vec::new(), u64::MAX).unwrap_err();
+        assert!(matches!(err, Error::Corrupt(_)), "{:?}", err);
+
+        let err = Lzh::read_input_limited(&mut &compressed[..], &mut Vec::new(), 10).unwrap_err();
+        assert!(matches!(err, Error::Corrupt(_)), "{:?}", err);
+    }
+}
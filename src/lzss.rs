@@ -1,6 +1,10 @@
-use std::io::{Read, self, Write, Result, ErrorKind};
-use crate::bitwriter::{BitWriter, self};
-use crate::bitreader::{BitReader, self};
+//! LZSS coding, which replaces repeated data with references back into a sliding window
+
+use std::io::{self, Read, Write, ErrorKind};
+use crate::bitwriter::BitWriter;
+use crate::bitreader::BitReader;
+use crate::codec::{Codec, Encoder};
+use crate::error::{truncated, Error, Result};
 use circular_buffer::CircularBuffer;
 
 pub enum Output {
@@ -8,8 +12,21 @@ pub enum Output {
     Reference{offset: u16, length: u8}
 }
 
-const BUFFER_SIZE: usize = 1_000_000;
+pub(crate) const BUFFER_SIZE: usize = 1_000_000;
+
+// Reference that marks the end of the data written by `LZSS::write_stream`
+pub(crate) const END_OF_STREAM: u16 = 0;
+
+/// Fastest compression level
+pub const MIN_LEVEL: u8 = 1;
+
+/// Best compression level, which searches the whole window
+pub const MAX_LEVEL: u8 = 9;
 
+// How far back each level searches for a match
+const LEVEL_WINDOWS: [usize; MAX_LEVEL as usize] = [64, 128, 256, 512, 1024, 1536, 2048, 3072, 4095];
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct LZSS;
 
 impl LZSS {
@@ -21,41 +38,109 @@ impl LZSS {
         reader: &mut R,
         writer: &mut W
     ) -> Result<()> {
-        let mut buffer = [0u8; BUFFER_SIZE];
+        Self::write_output_level(reader, writer, MAX_LEVEL)
+    }
+
+    /// Does the same thing as `write_output` but only searches as far back
+    /// as `level` allows. Lower levels are faster and compress less, the
+    /// output decodes the same way at every level
+    pub fn write_output_level<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        level: u8
+    ) -> Result<()> {
+        let mut writer = BitWriter::new(writer);
+        Self::write_tokens(reader, &mut writer, Self::window_for(level))?;
+        writer.flush()
+    }
+
+    /// Does the same thing as `write_output_level` but ends the data with
+    /// a marker, so it can be decoded without knowing its length up front
+    ///
+    /// The reader is read until each buffer is full, so a pipe that hands
+    /// out a few KB at a time compresses the same as a file
+    pub fn write_stream<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        level: u8
+    ) -> Result<()> {
         let mut writer = BitWriter::new(writer);
+        Self::write_tokens(reader, &mut writer, Self::window_for(level))?;
+        Self::write_end(&mut writer)?;
+        writer.flush()
+    }
+
+    // Marks the end of the data with a reference no real match can have
+    fn write_end<W: Write>(writer: &mut BitWriter<W>) -> Result<()> {
+        writer.write_bit(false)?;
+        writer.write_u16(END_OF_STREAM)
+    }
 
-        // Read into the buffer until reader is empty - in each read 
-        // encode the data and write it to the output
-        while let Ok(len) = reader.read(&mut buffer) {
+    // Encodes everything in the reader one buffer at a time
+    fn write_tokens<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut BitWriter<W>,
+        window: usize
+    ) -> Result<()> {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+
+        // Fill the buffer until reader is empty - each full buffer is
+        // encoded and written to the output, so the output does not depend
+        // on how the reader splits up its reads
+        loop {
+            let len = fill_buffer(reader, &mut buffer)?;
             if len == 0 { break; }
 
-            println!("Read into buffer");
-            let encoded_output = Self::encode(&buffer, len);
-            for val in encoded_output.into_iter() {
-                match val {
-                    Output::Literal(val) => {
-                        print!("{}", std::char::from_u32(val as u32).unwrap());
-                        writer.write_bit(true)?;
-                        writer.write_u8(val)?;
-                    },
-                    Output::Reference { offset, length } => {
-                        print!("<{},{}>", offset, length);
-                        writer.write_bit(false)?;
-                        // Offsets by 4 since the length is 4 bytes in size
-                        let double_byte = offset << 4 | length as u16;
-                        writer.write_u16(double_byte)?;
-                    },
-                }
-            }
+            Self::write_block(&buffer[..len], writer, window)?;
+        }
 
+        Ok(())
+    }
+
+    // Encodes one buffer, references never reach into an earlier one
+    fn write_block<W: Write>(
+        data: &[u8],
+        writer: &mut BitWriter<W>,
+        window: usize
+    ) -> Result<()> {
+        let encoded_output = Self::encode_window(data, data.len(), window);
+        for val in encoded_output.into_iter() {
+            match val {
+                Output::Literal(val) => {
+                    writer.write_bit(true)?;
+                    writer.write_u8(val)?;
+                },
+                Output::Reference { offset, length } => {
+                    writer.write_bit(false)?;
+                    // Offsets by 4 since the length is 4 bytes in size
+                    let double_byte = offset << 4 | length as u16;
+                    writer.write_u16(double_byte)?;
+                },
+            }
         }
-        writer.flush()?;
 
         Ok(())
     }
 
-    fn encode(data: &[u8], len: usize) -> Vec<Output> {
-        let (max_search_size, look_ahead_size) = (4095, 15);
+    // Returns the search window for a level, clamped to the valid range
+    fn window_for(level: u8) -> usize {
+        LEVEL_WINDOWS[(level.clamp(MIN_LEVEL, MAX_LEVEL) - 1) as usize]
+    }
+
+    /// Splits the first `len` bytes of `data` into literals and references
+    /// at the best level
+    pub fn encode(data: &[u8], len: usize) -> Vec<Output> {
+        Self::encode_window(data, len, Self::window_for(MAX_LEVEL))
+    }
+
+    /// Splits `data` into literals and references, searching as far back
+    /// as `level` allows
+    pub fn encode_level(data: &[u8], level: u8) -> Vec<Output> {
+        Self::encode_window(data, data.len(), Self::window_for(level))
+    }
+
+    fn encode_window(data: &[u8], len: usize, max_search_size: usize) -> Vec<Output> {
+        let look_ahead_size = 15;
         let mut output: Vec<Output> = Vec::with_capacity(len);
 
         // Sliding window
@@ -64,12 +149,12 @@ impl LZSS {
             let byte = data[pos];
 
             // Set search buffer bounds
-            let min = pos.checked_sub(max_search_size).unwrap_or(0);
+            let min = pos.saturating_sub(max_search_size);
             let max = std::cmp::min(len, pos + look_ahead_size);
             // Search the search buffer for a reference
             let (offset, length) = Self::search_buffer(
                 &data[min..max],
-                pos
+                pos - min
             );
 
             // Set the return value as a byte or reference
@@ -119,56 +204,244 @@ impl LZSS {
         (offset as u16, length as u8)
     }
 
+    /// Reads LZSS encoded data from a reader that implements `Read`
+    /// and writes the decoded bytes to a writer that implements `Write`
     /// 
+    /// Returns [`Result<()>`] if it fails to write
     pub fn read_input<R: Read, W: Write>(
         reader: &mut R,
         writer: &mut W
     ) -> Result<()> {
-        let mut reader = BitReader::new(reader);
-        let mut sliding_window = CircularBuffer::<4095, u8>::new();
+        Self::read_input_limited(reader, writer, u64::MAX)
+    }
 
-        println!("{}", "Starting read of input");
-        loop {
-            if let Ok(flag) = reader.read_bit() {
-                match flag {
-                    1 => match reader.read_byte() {
-                        Ok(literal) => { 
-                            println!("{}", literal);
-                            writer.write(&[literal])?; 
-                            sliding_window.push_front(literal);
-                        },
-                        Err(_) => break,
-                    },
-                    0 => match reader.read_u16() {
-                        Ok(reference) => {
-                            let offset = (reference >> 4) as usize;
-                            let length = (reference & 0b1111) as usize;
-
-                            println!("{},{}", offset, length);
-
-                            if offset >= sliding_window.capacity() {
-                                break;
-                            }
-
-                            sliding_window.range((offset - length)..(offset))
-                                .rev()
-                                .copied()
-                                .collect::<Vec<u8>>()
-                                .into_iter()
-                                .for_each(|literal| {
-                                    writer.write(&[literal]);
-                                    sliding_window.push_front(literal);
-                                });
-                        },
-                        Err(_) => break,
-                    },
-                    _ => unreachable!()
-                }
-            } else {
-                break;
-            }
+    /// Does the same thing as `read_input` but stops once `limit` bytes
+    /// are decoded or the end marker of `write_stream` is reached, leaving
+    /// anything after the encoded data in the reader
+    pub fn read_input_limited<R: Read, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+        limit: u64
+    ) -> Result<()> {
+        let mut tokens = Tokens::new(reader);
+        let mut decoded = Vec::with_capacity(16);
+
+        let mut written = 0u64;
+        while written < limit && tokens.next(&mut decoded)? {
+            writer.write_all(&decoded)?;
+            written += decoded.len() as u64;
+            decoded.clear();
+        }
+
+        Ok(())
+    }
+}
+
+// Reads until the buffer is full or the reader is empty
+pub(crate) fn fill_buffer<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize> {
+    let mut len = 0;
+    while len < buffer.len() {
+        match reader.read(&mut buffer[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(len)
+}
+
+// Decodes one literal or reference at a time, keeping the window of
+// recent output that references point into
+struct Tokens<R: Read> {
+    reader: BitReader<R>,
+    window: Window,
+}
+
+impl<R: Read> Tokens<R> {
+    fn new(reader: R) -> Tokens<R> {
+        Tokens {
+            reader: BitReader::new(reader),
+            window: Window::new(),
         }
+    }
+
+    // Appends the bytes of the next token to `decoded`. Returns false
+    // once the data ends at the end marker or between tokens
+    fn next(&mut self, decoded: &mut Vec<u8>) -> Result<bool> {
+        let Some(flag) = token_flag(self.reader.read_bit())? else {
+            return Ok(false);
+        };
+        if flag {
+            let literal = self.reader.read_byte().map_err(truncated)?;
+            self.window.literal(literal, decoded);
+            return Ok(true);
+        }
+
+        let Some(reference) = token_reference(self.reader.read_u16())? else {
+            return Ok(false);
+        };
+        self.window.reference(reference, decoded)?;
+        Ok(true)
+    }
+}
+
+// Returns whether the next token is a literal from the result of reading
+// its flag bit, or None if the data ended between tokens
+pub(crate) fn token_flag(read: Result<u8>) -> Result<Option<bool>> {
+    match read {
+        Ok(flag) => Ok(Some(flag == 1)),
+        Err(e) if e.is_eof() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Returns the reference from the result of reading its two bytes, or None
+// if it is the end marker or the padding at the end of the data
+pub(crate) fn token_reference(read: Result<u16>) -> Result<Option<u16>> {
+    match read {
+        // Real references never have an offset of 0
+        Ok(END_OF_STREAM) => Ok(None),
+        Ok(reference) => Ok(Some(reference)),
+        // Data without an end marker is padded with zero bits, which
+        // read as the start of a reference
+        Err(e) if e.is_eof() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// The recent output that references point into, shared by the sync and
+// async decoders
+pub(crate) struct Window {
+    buffer: CircularBuffer<4095, u8>,
+}
+
+impl Window {
+    pub(crate) fn new() -> Window {
+        Window { buffer: CircularBuffer::new() }
+    }
+
+    // Appends a literal to `decoded`
+    pub(crate) fn literal(&mut self, literal: u8, decoded: &mut Vec<u8>) {
+        decoded.push(literal);
+        self.buffer.push_front(literal);
+    }
+
+    // Appends the bytes a reference points at to `decoded`
+    pub(crate) fn reference(&mut self, reference: u16, decoded: &mut Vec<u8>) -> Result<()> {
+        let offset = (reference >> 4) as usize;
+        let length = (reference & 0b1111) as usize;
+
+        if offset > self.buffer.len() || length > offset {
+            return Err(Error::Corrupt(format!(
+                "Data is corrupt: reference to {} bytes at {} back with {} decoded", length, offset, self.buffer.len()
+            )));
+        }
+
+        // Indexed one at a time as `range` is wrong once the buffer wraps
+        let start = decoded.len();
+        decoded.extend(((offset - length)..(offset))
+            .rev()
+            .filter_map(|i| self.buffer.get(i).copied()));
+        for literal in &decoded[start..] {
+            self.buffer.push_front(*literal);
+        }
+        Ok(())
+    }
+}
+
+impl Codec for LZSS {
+    type Encoder<W: Write> = LzssEncoder<W>;
+    type Decoder<R: Read> = LzssDecoder<R>;
+
+    fn encoder<W: Write>(&self, writer: W) -> LzssEncoder<W> {
+        LzssEncoder {
+            writer: BitWriter::new(writer),
+            buffer: Vec::new(),
+            window: LZSS::window_for(MAX_LEVEL),
+        }
+    }
+
+    fn decoder<R: Read>(&self, reader: R) -> LzssDecoder<R> {
+        LzssDecoder {
+            tokens: Tokens::new(reader),
+            decoded: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+}
+
+/// Compresses everything written to it with LZSS
+///
+/// The output is the same as [`LZSS::write_stream`] at the best level
+pub struct LzssEncoder<W: Write> {
+    writer: BitWriter<W>,
+    buffer: Vec<u8>,
+    window: usize,
+}
 
+impl<W: Write> LzssEncoder<W> {
+    fn write_buffer(&mut self) -> Result<()> {
+        LZSS::write_block(&self.buffer, &mut self.writer, self.window)?;
+        self.buffer.clear();
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+impl<W: Write> Write for LzssEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(BUFFER_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == BUFFER_SIZE {
+            self.write_buffer()?;
+        }
+        Ok(len)
+    }
+
+    // Encodes what is buffered so far, which costs some compression as
+    // later data cannot refer back into it
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.write_buffer()?;
+        }
+        self.writer.get_mut().flush()
+    }
+}
+
+impl<W: Write> Encoder<W> for LzssEncoder<W> {
+    fn finish(mut self) -> Result<W> {
+        if !self.buffer.is_empty() {
+            self.write_buffer()?;
+        }
+        LZSS::write_end(&mut self.writer)?;
+        self.writer.into_inner()
+    }
+}
+
+/// Decompresses LZSS data written by [`LzssEncoder`] or [`LZSS::write_stream`]
+pub struct LzssDecoder<R: Read> {
+    tokens: Tokens<R>,
+    // Output of the last token that has not been read yet
+    decoded: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> Read for LzssDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.decoded.len() {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            self.decoded.clear();
+            self.pos = 0;
+            self.done = !self.tokens.next(&mut self.decoded)?;
+        }
+
+        let len = buf.len().min(self.decoded.len() - self.pos);
+        buf[..len].copy_from_slice(&self.decoded[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
@@ -1,4 +1,9 @@
-use std::array::from_fn;
+//! Fixed size maps keyed by byte
+
+use std::array::{self, from_fn};
+use std::iter::{Enumerate, FromIterator, Map};
+use std::ops::{AddAssign, Index, IndexMut};
+use std::slice;
 use serde::{Serialize, Deserialize, de::DeserializeOwned};
 use serde_big_array::BigArray;
 
@@ -40,12 +45,98 @@ impl<T> ByteMap<T>
     pub fn modify_or_default<F>(&mut self, idx: u8, closure: F, default: T) 
         where F: FnOnce(&mut T)
     {
-        if self.map[idx as usize].is_none() {
-            self.map[idx as usize] = Some(default);
-        } else {
-            closure(&mut self.map[idx as usize].unwrap());
+        match self.map[idx as usize].as_mut() {
+            Some(item) => closure(item),
+            None => self.map[idx as usize] = Some(default),
         }
     }
+
+    /// Iterates over all 256 slots as `(byte, slot)` pairs
+    pub fn iter(&self) -> Iter<'_, Option<T>> {
+        self.into_iter()
+    }
+
+    /// Iterates mutably over all 256 slots as `(byte, slot)` pairs
+    pub fn iter_mut(&mut self) -> IterMut<'_, Option<T>> {
+        self.into_iter()
+    }
+
+    /// Iterates over the bytes that have a value
+    pub fn iter_nonzero(&self) -> impl Iterator<Item = (u8, &T)> {
+        self.iter().filter_map(|(byte, item)| item.as_ref().map(|item| (byte, item)))
+    }
+}
+
+impl<T> Default for ByteMap<T>
+    where T: Clone + Default + Serialize + DeserializeOwned
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Index<u8> for ByteMap<T> 
+    where T: Clone + Default + Serialize + DeserializeOwned
+{
+    type Output = Option<T>;
+
+    fn index(&self, idx: u8) -> &Option<T> {
+        &self.map[idx as usize]
+    }
+}
+
+impl<T> IndexMut<u8> for ByteMap<T> 
+    where T: Clone + Default + Serialize + DeserializeOwned
+{
+    fn index_mut(&mut self, idx: u8) -> &mut Option<T> {
+        &mut self.map[idx as usize]
+    }
+}
+
+impl<T> IntoIterator for ByteMap<T> 
+    where T: Clone + Default + Serialize + DeserializeOwned
+{
+    type Item = (u8, Option<T>);
+    type IntoIter = IntoIter<Option<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter().enumerate().map(with_byte)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a ByteMap<T> 
+    where T: Clone + Default + Serialize + DeserializeOwned
+{
+    type Item = (u8, &'a Option<T>);
+    type IntoIter = Iter<'a, Option<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter().enumerate().map(with_byte)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut ByteMap<T> 
+    where T: Clone + Default + Serialize + DeserializeOwned
+{
+    type Item = (u8, &'a mut Option<T>);
+    type IntoIter = IterMut<'a, Option<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter_mut().enumerate().map(with_byte)
+    }
+}
+
+/// Later pairs overwrite earlier ones for the same byte
+impl<T> FromIterator<(u8, T)> for ByteMap<T> 
+    where T: Clone + Default + Serialize + DeserializeOwned
+{
+    fn from_iter<I: IntoIterator<Item = (u8, T)>>(iter: I) -> Self {
+        let mut map = ByteMap::new();
+        for (idx, item) in iter {
+            map.insert(item, idx);
+        }
+        map
+    }
 }
 
 pub struct CountMap {
@@ -78,6 +169,258 @@ impl CountMap {
 
     #[inline]
     pub fn get_copy(&self) -> [usize; 256] {
-        self.map.clone()
+        self.map
+    }
+
+    /// Iterates over all 256 bytes as `(byte, count)` pairs
+    pub fn iter(&self) -> Iter<'_, usize> {
+        self.into_iter()
+    }
+
+    /// Iterates mutably over all 256 bytes as `(byte, count)` pairs
+    pub fn iter_mut(&mut self) -> IterMut<'_, usize> {
+        self.into_iter()
+    }
+
+    /// Iterates over the bytes with a count above zero
+    pub fn iter_nonzero(&self) -> impl Iterator<Item = (u8, usize)> + '_ {
+        self.iter()
+            .filter(|(_, count)| **count > 0)
+            .map(|(byte, count)| (byte, *count))
+    }
+
+    /// Returns the sum of all counts
+    pub fn total(&self) -> usize {
+        self.map.iter().sum()
+    }
+
+    /// Returns the number of bytes with a count above zero
+    pub fn distinct(&self) -> usize {
+        self.map.iter().filter(|count| **count > 0).count()
+    }
+
+    /// Returns the Shannon entropy in bits per byte
+    ///
+    /// Ranges from 0.0 (one repeated byte) to 8.0 (uniformly random)
+    pub fn entropy(&self) -> f64 {
+        let total = self.total() as f64;
+        self.iter_nonzero()
+            .map(|(_, count)| {
+                let p = count as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+    /// Returns the smallest size in bytes an order-0 entropy coder
+    /// could compress the counted data to
+    pub fn min_compressed_size(&self) -> usize {
+        (self.entropy() * self.total() as f64 / 8.0).ceil() as usize
+    }
+
+    /// Returns the most frequent byte and its count
+    ///
+    /// Ties go to the lowest byte
+    pub fn most_frequent(&self) -> Option<(u8, usize)> {
+        self.iter_nonzero()
+            .fold(None, |best, (byte, count)| match best {
+                Some((_, best_count)) if best_count >= count => best,
+                _ => Some((byte, count)),
+            })
+    }
+
+    /// Returns the least frequent byte that occurs at least once and its count
+    ///
+    /// Ties go to the lowest byte
+    pub fn least_frequent(&self) -> Option<(u8, usize)> {
+        self.iter_nonzero()
+            .fold(None, |best, (byte, count)| match best {
+                Some((_, best_count)) if best_count <= count => best,
+                _ => Some((byte, count)),
+            })
+    }
+
+    /// Returns the chi-square statistic against a uniform distribution
+    ///
+    /// Random data lands close to 255 (the degrees of freedom), while
+    /// text and other structured data score far higher
+    pub fn chi_square(&self) -> f64 {
+        let expected = self.total() as f64 / 256.0;
+        if expected == 0.0 {
+            return 0.0;
+        }
+
+        self.map.iter()
+            .map(|count| {
+                let diff = *count as f64 - expected;
+                diff * diff / expected
+            })
+            .sum()
+    }
+
+    /// Adds the counts of another map to this one
+    #[inline]
+    pub fn merge(&mut self, other: &CountMap) {
+        for (count, other) in self.map.iter_mut().zip(other.map.iter()) {
+            *count += other;
+        }
+    }
+}
+
+impl Default for CountMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AddAssign<&CountMap> for CountMap {
+    fn add_assign(&mut self, other: &CountMap) {
+        self.merge(other);
+    }
+}
+
+impl AddAssign for CountMap {
+    fn add_assign(&mut self, other: CountMap) {
+        self.merge(&other);
+    }
+}
+
+impl Index<u8> for CountMap {
+    type Output = usize;
+
+    #[inline]
+    fn index(&self, idx: u8) -> &usize {
+        &self.map[idx as usize]
+    }
+}
+
+impl IndexMut<u8> for CountMap {
+    #[inline]
+    fn index_mut(&mut self, idx: u8) -> &mut usize {
+        &mut self.map[idx as usize]
+    }
+}
+
+impl IntoIterator for CountMap {
+    type Item = (u8, usize);
+    type IntoIter = IntoIter<usize>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter().enumerate().map(with_byte)
+    }
+}
+
+impl<'a> IntoIterator for &'a CountMap {
+    type Item = (u8, &'a usize);
+    type IntoIter = Iter<'a, usize>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter().enumerate().map(with_byte)
+    }
+}
+
+impl<'a> IntoIterator for &'a mut CountMap {
+    type Item = (u8, &'a mut usize);
+    type IntoIter = IterMut<'a, usize>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter_mut().enumerate().map(with_byte)
+    }
+}
+
+/// Counts for the same byte are added together
+impl FromIterator<(u8, usize)> for CountMap {
+    fn from_iter<I: IntoIterator<Item = (u8, usize)>>(iter: I) -> Self {
+        let mut map = CountMap::new();
+        for (idx, num) in iter {
+            map.increment_count_by(idx, num);
+        }
+        map
+    }
+}
+
+/// Owning iterator over the slots of a map
+pub type IntoIter<V> = Map<Enumerate<array::IntoIter<V, 256>>, fn((usize, V)) -> (u8, V)>;
+
+/// Borrowing iterator over the slots of a map
+pub type Iter<'a, V> = Map<Enumerate<slice::Iter<'a, V>>, fn((usize, &'a V)) -> (u8, &'a V)>;
+
+/// Mutable iterator over the slots of a map
+pub type IterMut<'a, V> = Map<Enumerate<slice::IterMut<'a, V>>, fn((usize, &'a mut V)) -> (u8, &'a mut V)>;
+
+// Turns a slot position into the byte it belongs to
+fn with_byte<V>((idx, item): (usize, V)) -> (u8, V) {
+    (idx as u8, item)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_map_iter() {
+        let mut map: ByteMap<String> = vec![(3, "a".to_string()), (200, "b".to_string())]
+            .into_iter()
+            .collect();
+
+        assert_eq!(map.iter().count(), 256);
+        assert_eq!(map[3], Some("a".to_string()));
+
+        map[3] = None;
+        for (_, item) in map.iter_mut() {
+            if let Some(item) = item {
+                item.push('!');
+            }
+        }
+
+        let present: Vec<_> = map.iter_nonzero().collect();
+        assert_eq!(present, vec![(200, &"b!".to_string())]);
+    }
+
+    #[test]
+    fn test_count_map_iter() {
+        let mut map: CountMap = vec![(1, 2), (1, 3), (255, 1)].into_iter().collect();
+        map[0] += 4;
+
+        assert_eq!(map[1], 5);
+        assert_eq!(map.iter_nonzero().collect::<Vec<_>>(), vec![(0, 4), (1, 5), (255, 1)]);
+
+        let total: usize = map.into_iter().map(|(_, count)| count).sum();
+        assert_eq!(total, 10);
+    }
+
+    #[test]
+    fn test_count_map_stats() {
+        let mut map: CountMap = vec![(b'a', 4), (b'b', 2), (b'c', 1), (b'd', 1)].into_iter().collect();
+
+        assert_eq!(map.total(), 8);
+        assert_eq!(map.distinct(), 4);
+        assert_eq!(map.entropy(), 1.75);
+        assert_eq!(map.min_compressed_size(), 2);
+        assert_eq!(map.most_frequent(), Some((b'a', 4)));
+        assert_eq!(map.least_frequent(), Some((b'c', 1)));
+
+        map += vec![(b'e', 8)].into_iter().collect::<CountMap>();
+        assert_eq!(map.most_frequent(), Some((b'e', 8)));
+        assert_eq!(map.total(), 16);
+    }
+
+    #[test]
+    fn test_count_map_empty_stats() {
+        let map = CountMap::new();
+
+        assert_eq!(map.entropy(), 0.0);
+        assert_eq!(map.chi_square(), 0.0);
+        assert_eq!(map.most_frequent(), None);
+        assert_eq!(map.least_frequent(), None);
+    }
+
+    #[test]
+    fn test_chi_square() {
+        let uniform: CountMap = (0..=255).map(|byte| (byte, 10)).collect();
+        assert_eq!(uniform.chi_square(), 0.0);
+
+        let single: CountMap = vec![(0, 256)].into_iter().collect();
+        assert_eq!(single.chi_square(), 255.0 * 256.0);
     }
 }
\ No newline at end of file
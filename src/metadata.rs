@@ -0,0 +1,383 @@
+//! File name, modification time, permissions and extended attributes kept alongside compressed data
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write, ErrorKind};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::{truncated, Error, Result};
+
+/// File metadata stored in a container header, like gzip's FNAME and MTIME
+///
+/// | field            | size          |
+/// |------------------|---------------|
+/// | name length      | 2 (BE)        |
+/// | name             | variable      |
+/// | mtime seconds    | 8 (BE)        |
+/// | mtime nanos      | 4 (BE)        |
+/// | mode             | 4 (BE)        |
+/// | xattr count      | 2 (BE)        |
+/// | per xattr        | name length 2 (BE), name, value length 4 (BE), value |
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// File name without any directories
+    pub name: String,
+    /// Modification time in seconds since the Unix epoch
+    pub mtime: i64,
+    pub mtime_nanos: u32,
+    /// Unix permission bits
+    pub mode: u32,
+    /// Extended attributes as raw name and value bytes
+    pub xattrs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// Mode bits restored unless [`Restore::privileged`] is set
+pub const PERMISSION_BITS: u32 = 0o777;
+
+// Extended attribute namespace restored unless `Restore::privileged` is set
+const USER_XATTR_PREFIX: &[u8] = b"user.";
+
+/// Which parts of the metadata are put back on decompression
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Restore {
+    pub name: bool,
+    pub mtime: bool,
+    pub mode: bool,
+    pub xattrs: bool,
+    /// Also restores the setuid, setgid and sticky bits and extended
+    /// attributes outside the `user.` namespace. Only for trusted input
+    pub privileged: bool,
+}
+
+impl Restore {
+    /// Restores everything except the privileged bits and attributes
+    pub fn all() -> Restore {
+        Restore { name: true, mtime: true, mode: true, xattrs: true, privileged: false }
+    }
+
+    /// Restores nothing
+    pub fn none() -> Restore {
+        Restore { name: false, mtime: false, mode: false, xattrs: false, privileged: false }
+    }
+}
+
+impl Default for Restore {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl Metadata {
+    /// Reads the metadata of a file
+    ///
+    /// Extended attributes are left out where the filesystem does not support them
+    pub fn from_path(path: &Path) -> Result<Metadata> {
+        let metadata = fs::metadata(path)?;
+        let (mtime, mtime_nanos) = mtime_of(&metadata);
+
+        Ok(Metadata {
+            name: path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            mtime,
+            mtime_nanos,
+            mode: mode_of(&metadata),
+            xattrs: read_xattrs(path)?,
+        })
+    }
+
+    /// Returns the modification time as a `SystemTime`
+    pub fn modified(&self) -> SystemTime {
+        let seconds = Duration::from_secs(self.mtime.unsigned_abs());
+        let base = match self.mtime >= 0 {
+            true => UNIX_EPOCH + seconds,
+            false => UNIX_EPOCH - seconds,
+        };
+        base + Duration::from_nanos(self.mtime_nanos as u64)
+    }
+
+    /// Puts the metadata selected by `restore` back on a file
+    ///
+    /// The name is not applied here, it decides where the output goes.
+    /// Extended attributes the filesystem or user cannot set are skipped
+    /// with a warning
+    pub fn restore(&self, path: &Path, restore: &Restore) -> Result<()> {
+        if restore.xattrs {
+            let xattrs = self.xattrs.iter()
+                .filter(|(name, _)| restore.privileged || name.starts_with(USER_XATTR_PREFIX));
+            write_xattrs(path, xattrs)?;
+        }
+        // The mode can take away write access, so it goes after anything else that writes
+        if restore.mtime {
+            File::options().write(true).open(path)?.set_modified(self.modified())?;
+        }
+        if restore.mode {
+            let mask = if restore.privileged { 0o7777 } else { PERMISSION_BITS };
+            set_mode(path, self.mode & mask)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the metadata to a writer
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write_bytes_u16(writer, self.name.as_bytes())?;
+        writer.write_all(&self.mtime.to_be_bytes())?;
+        writer.write_all(&self.mtime_nanos.to_be_bytes())?;
+        writer.write_all(&self.mode.to_be_bytes())?;
+
+        let count = u16::try_from(self.xattrs.len())
+            .map_err(|_| Error::InvalidParams("Too many extended attributes".to_string()))?;
+        writer.write_all(&count.to_be_bytes())?;
+        for (name, value) in &self.xattrs {
+            write_bytes_u16(writer, name)?;
+            let len = u32::try_from(value.len())
+                .map_err(|_| Error::InvalidParams("Extended attribute is too large".to_string()))?;
+            writer.write_all(&len.to_be_bytes())?;
+            writer.write_all(value)?;
+        }
+        Ok(())
+    }
+
+    /// Reads metadata written by [`Metadata::write_to`]
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Metadata> {
+        let name = String::from_utf8(read_bytes_u16(reader)?)
+            .map_err(|_| Error::Corrupt("File name is not UTF-8".to_string()))?;
+        let mtime = i64::from_be_bytes(read_array(reader)?);
+        let mtime_nanos = u32::from_be_bytes(read_array(reader)?);
+        if mtime_nanos >= 1_000_000_000 {
+            return Err(Error::Corrupt("Modification time is out of range".to_string()));
+        }
+        let mode = u32::from_be_bytes(read_array(reader)?);
+
+        let count = u16::from_be_bytes(read_array(reader)?);
+        let mut xattrs = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let name = read_bytes_u16(reader)?;
+            let len = u32::from_be_bytes(read_array(reader)?);
+            let mut value = Vec::new();
+            reader.by_ref().take(len as u64).read_to_end(&mut value)?;
+            if value.len() != len as usize {
+                return Err(Error::Corrupt("Extended attribute is cut short".to_string()));
+            }
+            xattrs.push((name, value));
+        }
+
+        Ok(Metadata { name, mtime, mtime_nanos, mode, xattrs })
+    }
+}
+
+/// Returns the modification time of a file as seconds and nanoseconds since the Unix epoch
+pub(crate) fn mtime_of(metadata: &fs::Metadata) -> (i64, u32) {
+    match metadata.modified() {
+        Ok(time) => match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => (since.as_secs() as i64, since.subsec_nanos()),
+            Err(before) => {
+                let before = before.duration();
+                match before.subsec_nanos() {
+                    0 => (-(before.as_secs() as i64), 0),
+                    nanos => (-(before.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+                }
+            },
+        },
+        Err(_) => (0, 0),
+    }
+}
+
+/// Returns the Unix permission bits of a file
+#[cfg(unix)]
+pub(crate) fn mode_of(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+/// Returns the Unix permission bits of a file
+#[cfg(not(unix))]
+pub(crate) fn mode_of(metadata: &fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() { 0o444 } else { 0o644 }
+}
+
+/// Sets the Unix permission bits of a file
+#[cfg(unix)]
+pub(crate) fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+/// Sets the Unix permission bits of a file
+#[cfg(not(unix))]
+pub(crate) fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o200 == 0);
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(unix)]
+fn read_xattrs(path: &Path) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let names = match xattr::list(path) {
+        Ok(names) => names,
+        Err(e) if e.kind() == ErrorKind::Unsupported => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut xattrs = Vec::new();
+    for name in names {
+        if let Some(value) = xattr::get(path, &name)? {
+            xattrs.push((name.as_bytes().to_vec(), value));
+        }
+    }
+    xattrs.sort();
+    Ok(xattrs)
+}
+
+#[cfg(not(unix))]
+fn read_xattrs(_path: &Path) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    Ok(Vec::new())
+}
+
+// Attributes the filesystem does not support or the user may not set are
+// skipped, like `read_xattrs` skips a filesystem without any
+#[cfg(unix)]
+fn write_xattrs<'a, I>(path: &Path, xattrs: I) -> io::Result<()>
+where
+    I: Iterator<Item = &'a (Vec<u8>, Vec<u8>)>
+{
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    for (name, value) in xattrs {
+        let name = OsStr::from_bytes(name);
+        match xattr::set(path, name, value) {
+            Ok(()) => {},
+            Err(e) if matches!(e.kind(), ErrorKind::Unsupported | ErrorKind::PermissionDenied) => {
+                eprintln!("file_compressor: {}: cannot set extended attribute {}: {}",
+                    path.display(), name.to_string_lossy(), e);
+            },
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+// Extended attributes recorded on a Unix system are dropped elsewhere
+#[cfg(not(unix))]
+fn write_xattrs<'a, I>(_path: &Path, _xattrs: I) -> io::Result<()>
+where
+    I: Iterator<Item = &'a (Vec<u8>, Vec<u8>)>
+{
+    Ok(())
+}
+
+fn write_bytes_u16<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<()> {
+    let len = u16::try_from(bytes.len())
+        .map_err(|_| Error::InvalidParams("Name is too long".to_string()))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_bytes_u16<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut bytes = vec![0u8; u16::from_be_bytes(read_array(reader)?) as usize];
+    reader.read_exact(&mut bytes).map_err(|e| truncated(e.into()))?;
+    Ok(bytes)
+}
+
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes).map_err(|e| truncated(e.into()))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let metadata = Metadata {
+            name: "notes.txt".to_string(),
+            mtime: 1_700_000_000,
+            mtime_nanos: 123_456_789,
+            mode: 0o640,
+            xattrs: vec![(b"user.origin".to_vec(), b"somewhere".to_vec())],
+        };
+
+        let mut bytes = Vec::new();
+        metadata.write_to(&mut bytes).unwrap();
+        assert_eq!(Metadata::read_from(&mut &bytes[..]).unwrap(), metadata);
+    }
+
+    #[test]
+    fn test_modified_before_epoch() {
+        let metadata = Metadata { mtime: -2, mtime_nanos: 500_000_000, ..Default::default() };
+        assert_eq!(metadata.modified(), UNIX_EPOCH - Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn test_rejects_truncated() {
+        let mut bytes = Vec::new();
+        Metadata { name: "a".to_string(), ..Default::default() }.write_to(&mut bytes).unwrap();
+        bytes.pop();
+
+        assert!(Metadata::read_from(&mut &bytes[..]).is_err());
+    }
+
+    #[cfg(unix)]
+    fn scratch(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("file_compressor_metadata_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_masks_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch("mode");
+        let path = dir.join("tool");
+        fs::write(&path, b"#!/bin/sh").unwrap();
+        let metadata = Metadata { mode: 0o4755, ..Default::default() };
+
+        metadata.restore(&path, &Restore::all()).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o7777, 0o755);
+
+        metadata.restore(&path, &Restore { privileged: true, ..Restore::all() }).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o7777, 0o4755);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_restore_xattrs_best_effort() {
+        let dir = scratch("xattrs");
+        let path = dir.join("notes.txt");
+        fs::write(&path, b"notes").unwrap();
+        let metadata = Metadata {
+            xattrs: vec![
+                (b"system.bogus".to_vec(), b"x".to_vec()),
+                (b"trusted.origin".to_vec(), b"somewhere".to_vec()),
+                (b"user.origin".to_vec(), b"somewhere".to_vec()),
+            ],
+            ..Default::default()
+        };
+
+        // Not every filesystem has user xattrs
+        let supported = xattr::set(&path, "user.probe", b"").is_ok();
+
+        // Only user attributes by default
+        metadata.restore(&path, &Restore::all()).unwrap();
+        assert_eq!(xattr::get(&path, "trusted.origin").unwrap_or(None), None);
+        if supported {
+            assert_eq!(xattr::get(&path, "user.origin").unwrap(), Some(b"somewhere".to_vec()));
+        }
+
+        // Attributes that cannot be set are skipped instead of failing the restore
+        metadata.restore(&path, &Restore { privileged: true, ..Restore::all() }).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+//! Chains of transforms and codecs, like delta then LZSS then Huffman
+
+use std::fmt::{self, Display};
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use crate::codec::{Codec, Encoder};
+use crate::error::{Error, Result};
+use crate::huffman::Huffman;
+use crate::lzss::LZSS;
+
+/// Most stages a pipeline can have
+pub const MAX_STAGES: usize = 8;
+
+// Shortest and longest run, and longest stretch of literals, one RLE control byte covers
+const MIN_RUN: usize = 3;
+const MAX_RUN: usize = 130;
+const MAX_LITERALS: usize = 128;
+
+/// One step of a [`Pipeline`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Replaces every byte with its difference from the one before, which
+    /// turns slowly changing data like samples or tables into small values
+    Delta,
+    /// Run-length encoding in the PackBits style
+    Rle,
+    /// [`LZSS`] with an end marker
+    Lzss,
+    /// [`Huffman`] with the length and tree in front
+    Huffman,
+}
+
+impl Stage {
+    /// Every stage, in the order of their ids
+    pub const ALL: [Stage; 4] = [Stage::Delta, Stage::Rle, Stage::Lzss, Stage::Huffman];
+
+    /// Returns the id written to the header
+    pub fn id(self) -> u8 {
+        match self {
+            Stage::Delta => 1,
+            Stage::Rle => 2,
+            Stage::Lzss => 3,
+            Stage::Huffman => 4,
+        }
+    }
+
+    /// Returns the stage for a header id
+    pub fn from_id(id: u8) -> Option<Stage> {
+        Stage::ALL.into_iter().find(|stage| stage.id() == id)
+    }
+
+    /// Returns the name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Delta => "delta",
+            Stage::Rle => "rle",
+            Stage::Lzss => "lzss",
+            Stage::Huffman => "huffman",
+        }
+    }
+
+    /// Runs the stage forwards. `level` is only used by LZSS
+    pub fn encode(self, data: &[u8], level: u8) -> Result<Vec<u8>> {
+        match self {
+            Stage::Delta => Ok(delta_encode(data)),
+            Stage::Rle => Ok(rle_encode(data)),
+            Stage::Lzss => {
+                let mut output = Vec::new();
+                LZSS::write_stream(&mut &data[..], &mut output, level)?;
+                Ok(output)
+            },
+            Stage::Huffman => {
+                let mut encoder = Huffman.encoder(Vec::new());
+                encoder.write_all(data)?;
+                encoder.finish()
+            },
+        }
+    }
+
+    /// Undoes [`Stage::encode`]
+    pub fn decode(self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        match self {
+            Stage::Delta => return Ok(delta_decode(data)),
+            Stage::Rle => return rle_decode(data),
+            Stage::Lzss => LZSS.decoder(data).read_to_end(&mut output)?,
+            Stage::Huffman => Huffman.decoder(data).read_to_end(&mut output)?,
+        };
+        Ok(output)
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Stage {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Stage> {
+        Stage::ALL.into_iter()
+            .find(|stage| stage.name().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| {
+                let names: Vec<_> = Stage::ALL.iter().map(|stage| stage.name()).collect();
+                Error::InvalidParams(format!("Unknown stage {:?}, expected one of {}", name, names.join(", ")))
+            })
+    }
+}
+
+/// Stages run one after another on the whole input, and in reverse to decode
+///
+/// ```
+/// use file_compressor::pipeline::Pipeline;
+///
+/// let pipeline: Pipeline = "delta,lzss,huffman".parse().unwrap();
+/// let data: Vec<u8> = (0..1000u32).map(|i| (i / 3) as u8).collect();
+///
+/// let encoded = pipeline.encode(&data, 9).unwrap();
+/// assert!(encoded.len() < data.len() / 4);
+/// assert_eq!(pipeline.decode(&encoded).unwrap(), data);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// Instantiates a pipeline of one to [`MAX_STAGES`] stages
+    pub fn new(stages: Vec<Stage>) -> Result<Pipeline> {
+        if stages.is_empty() || stages.len() > MAX_STAGES {
+            return Err(Error::InvalidParams(format!(
+                "A pipeline needs 1 to {} stages, not {}", MAX_STAGES, stages.len()
+            )));
+        }
+        Ok(Pipeline { stages })
+    }
+
+    /// Returns the stages in the order they encode
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// Returns the stage ids written to the header
+    pub fn params(&self) -> Vec<u8> {
+        self.stages.iter().map(|stage| stage.id()).collect()
+    }
+
+    /// Returns the pipeline for the stage ids in a header
+    pub fn from_params(params: &[u8]) -> Result<Pipeline> {
+        let stages = params.iter()
+            .map(|&id| Stage::from_id(id).ok_or_else(|| Error::Unsupported(format!("Unknown pipeline stage id {}", id))))
+            .collect::<Result<Vec<_>>>()?;
+        Pipeline::new(stages).map_err(|e| Error::Unsupported(e.to_string()))
+    }
+
+    /// Runs every stage in order
+    pub fn encode(&self, data: &[u8], level: u8) -> Result<Vec<u8>> {
+        let mut data = self.stages[0].encode(data, level)?;
+        for stage in &self.stages[1..] {
+            data = stage.encode(&data, level)?;
+        }
+        Ok(data)
+    }
+
+    /// Runs every stage backwards, starting with the last
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let (last, rest) = self.stages.split_last().expect("a pipeline has stages");
+        let mut data = last.decode(data)?;
+        for stage in rest.iter().rev() {
+            data = stage.decode(&data)?;
+        }
+        Ok(data)
+    }
+}
+
+impl Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, stage) in self.stages.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            stage.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Pipeline {
+    type Err = Error;
+
+    fn from_str(stages: &str) -> Result<Pipeline> {
+        Pipeline::new(stages.split(',').map(str::parse).collect::<Result<_>>()?)
+    }
+}
+
+fn delta_encode(data: &[u8]) -> Vec<u8> {
+    let mut previous = 0u8;
+    data.iter()
+        .map(|&byte| {
+            let delta = byte.wrapping_sub(previous);
+            previous = byte;
+            delta
+        })
+        .collect()
+}
+
+fn delta_decode(data: &[u8]) -> Vec<u8> {
+    let mut previous = 0u8;
+    data.iter()
+        .map(|&delta| {
+            previous = previous.wrapping_add(delta);
+            previous
+        })
+        .collect()
+}
+
+// A control byte below 128 is followed by that many plus one literals,
+// anything else repeats the next byte 125 times fewer than its value
+fn rle_encode(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() + data.len() / MAX_LITERALS + 1);
+    let mut literals = 0..0;
+    let mut i = 0;
+
+    while i < data.len() {
+        let run = data[i..].iter()
+            .take(MAX_RUN)
+            .take_while(|&&byte| byte == data[i])
+            .count();
+
+        if run >= MIN_RUN {
+            write_literals(&mut output, &data[literals]);
+            output.push((run + 125) as u8);
+            output.push(data[i]);
+            i += run;
+            literals = i..i;
+        } else {
+            i += 1;
+            literals.end = i;
+            if literals.len() == MAX_LITERALS {
+                write_literals(&mut output, &data[literals]);
+                literals = i..i;
+            }
+        }
+    }
+    write_literals(&mut output, &data[literals]);
+    output
+}
+
+fn write_literals(output: &mut Vec<u8>, literals: &[u8]) {
+    if !literals.is_empty() {
+        output.push((literals.len() - 1) as u8);
+        output.extend_from_slice(literals);
+    }
+}
+
+fn rle_decode(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut i = 0;
+
+    while i < data.len() {
+        let control = data[i] as usize;
+        i += 1;
+        let (len, end) = match control < MAX_LITERALS {
+            true => (control + 1, i + control + 1),
+            false => (control - 125, i + 1),
+        };
+        let Some(bytes) = data.get(i..end) else {
+            return Err(Error::Corrupt("Data is corrupt: run-length data ends early".to_string()));
+        };
+        match control < MAX_LITERALS {
+            true => output.extend_from_slice(bytes),
+            false => output.resize(output.len() + len, bytes[0]),
+        }
+        i = end;
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lzss.rs")).unwrap()
+    }
+
+    #[test]
+    fn test_stages_round_trip() {
+        let text = sample();
+        let runs: Vec<u8> = (0..2000).map(|i| (i / 200) as u8).collect();
+        for data in [&text[..], &runs[..], b"", b"a", b"aaa", b"abcabc"] {
+            for stage in Stage::ALL {
+                let encoded = stage.encode(data, 9).unwrap();
+                assert_eq!(stage.decode(&encoded).unwrap(), data, "{}", stage);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rle_lengths() {
+        // Runs and literals right at and past what one control byte holds
+        for len in [1, 2, 3, 127, 128, 129, 130, 131, 260, 261] {
+            let run = vec![7u8; len];
+            assert_eq!(rle_decode(&rle_encode(&run)).unwrap(), run);
+            let literals: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            assert_eq!(rle_decode(&rle_encode(&literals)).unwrap(), literals);
+        }
+        assert_eq!(rle_encode(&[5; 10]), [135, 5]);
+        assert!(matches!(rle_decode(&[3, 1, 2]), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn test_pipeline_round_trip() {
+        let data = sample();
+        for stages in ["lzss,huffman", "delta,rle,huffman", "huffman,huffman", "rle"] {
+            let pipeline: Pipeline = stages.parse().unwrap();
+            assert_eq!(pipeline.to_string(), stages);
+            let encoded = pipeline.encode(&data, 9).unwrap();
+            assert_eq!(pipeline.decode(&encoded).unwrap(), data, "{}", stages);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let pipeline: Pipeline = "Delta, lzss".parse().unwrap();
+        assert_eq!(pipeline.stages(), [Stage::Delta, Stage::Lzss]);
+        assert_eq!(Pipeline::from_params(&pipeline.params()).unwrap(), pipeline);
+
+        assert!(matches!("lzss,zip".parse::<Pipeline>(), Err(Error::InvalidParams(_))));
+        assert!(matches!("".parse::<Pipeline>(), Err(Error::InvalidParams(_))));
+        assert!(matches!(Pipeline::from_params(&[3, 99]), Err(Error::Unsupported(_))));
+        assert!(matches!(Pipeline::from_params(&[]), Err(Error::Unsupported(_))));
+    }
+}
@@ -0,0 +1,383 @@
+//! A framed format where any range can be read without decompressing the rest
+
+use std::io::{self, Read, Write, Seek, SeekFrom, ErrorKind};
+
+use crate::container::{self, Algorithm, Header};
+use crate::error::{truncated, Error, Result};
+
+/// Bytes every seekable file starts with
+pub const MAGIC: [u8; 4] = *b"FCSF";
+
+/// Bytes every seekable file ends with
+pub const END_MAGIC: [u8; 4] = *b"FCSE";
+
+/// Newest seekable format version this build can read and the one it writes
+pub const VERSION: u8 = 1;
+
+/// Amount of input compressed into each frame unless one is given
+pub const DEFAULT_FRAME_SIZE: usize = 1 << 20;
+
+const HEADER_SIZE: u64 = 5;
+const FOOTER_SIZE: u64 = 8;
+const TABLE_ENTRY_SIZE: u64 = 16;
+
+/// One independently compressed frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// Where the frame starts in the compressed file
+    pub compressed_offset: u64,
+    pub compressed_size: u64,
+    /// Where the frame's data starts in the uncompressed content
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Maps uncompressed offsets to the frames holding them
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SeekTable {
+    frames: Vec<Frame>,
+}
+
+impl SeekTable {
+    /// Returns every frame in order
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Returns the size of the uncompressed content
+    pub fn len(&self) -> u64 {
+        self.frames.last().map_or(0, |frame| frame.offset + frame.size)
+    }
+
+    /// Returns true if there is no content
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the index of the frame holding the byte at `offset`
+    pub fn find(&self, offset: u64) -> Option<usize> {
+        let index = self.frames.partition_point(|frame| frame.offset + frame.size <= offset);
+        (index < self.frames.len()).then_some(index)
+    }
+
+    // Sizes come from the file, so a frame that ends past u64::MAX means
+    // it is corrupt. Checking every end here keeps `len` and `find` safe
+    fn push(&mut self, compressed_size: u64, size: u64) -> Result<()> {
+        let (compressed_offset, offset) = match self.frames.last() {
+            Some(last) => (last.compressed_offset + last.compressed_size, last.offset + last.size),
+            None => (HEADER_SIZE, 0),
+        };
+        if compressed_offset.checked_add(compressed_size).is_none() || offset.checked_add(size).is_none() {
+            return Err(invalid("Seek table offsets overflow"));
+        }
+        self.frames.push(Frame { compressed_offset, compressed_size, offset, size });
+        Ok(())
+    }
+
+    // Writes one entry per frame followed by the footer
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        for frame in &self.frames {
+            writer.write_all(&frame.compressed_size.to_be_bytes())?;
+            writer.write_all(&frame.size.to_be_bytes())?;
+        }
+        writer.write_all(&(self.frames.len() as u32).to_be_bytes())?;
+        writer.write_all(&END_MAGIC)?;
+        Ok(())
+    }
+
+    /// Reads the header and the seek table at the end of a seekable file
+    pub fn read_from<R: Read + Seek>(reader: &mut R) -> Result<SeekTable> {
+        reader.seek(SeekFrom::Start(0))?;
+        let [m0, m1, m2, m3, version] = read_array(reader)?;
+        if [m0, m1, m2, m3] != MAGIC {
+            return Err(invalid("Not a seekable file (bad magic bytes)"));
+        }
+        if version == 0 || version > VERSION {
+            return Err(Error::Unsupported(format!(
+                "Unsupported seekable version {} (newest supported is {})", version, VERSION
+            )));
+        }
+
+        let end = reader.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+        let count = u32::from_be_bytes(read_array(reader)?) as u64;
+        if read_array(reader)? != END_MAGIC {
+            return Err(invalid("Seek table is missing"));
+        }
+
+        let start = end.checked_sub(count * TABLE_ENTRY_SIZE)
+            .filter(|start| *start >= HEADER_SIZE)
+            .ok_or_else(|| invalid("Seek table is larger than the file"))?;
+        reader.seek(SeekFrom::Start(start))?;
+
+        let mut table = SeekTable::default();
+        for _ in 0..count {
+            let compressed_size = u64::from_be_bytes(read_array(reader)?);
+            let size = u64::from_be_bytes(read_array(reader)?);
+            table.push(compressed_size, size)?;
+        }
+
+        let data_end = table.frames.last().map_or(HEADER_SIZE, |frame| frame.compressed_offset + frame.compressed_size);
+        if data_end != start {
+            return Err(invalid("Seek table does not match the frames"));
+        }
+
+        Ok(table)
+    }
+}
+
+/// Compresses a reader into independent frames of `frame_size` bytes
+/// followed by a seek table
+///
+/// Only one frame is held in memory at a time
+pub fn compress<R: Read, W: Write>(reader: &mut R, writer: &mut W, algorithm: Algorithm, frame_size: usize) -> Result<SeekTable> {
+    if frame_size == 0 {
+        return Err(Error::InvalidParams("Frame size of zero".to_string()));
+    }
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&[VERSION])?;
+
+    let mut table = SeekTable::default();
+    let mut data = Vec::with_capacity(frame_size);
+    let mut frame = Vec::new();
+    loop {
+        data.clear();
+        reader.by_ref().take(frame_size as u64).read_to_end(&mut data)?;
+        if data.is_empty() {
+            break;
+        }
+
+        frame.clear();
+        container::compress(&mut &data[..], &mut frame, algorithm)?;
+        writer.write_all(&frame)?;
+        table.push(frame.len() as u64, data.len() as u64)?;
+    }
+
+    table.write_to(writer)?;
+    writer.flush()?;
+    Ok(table)
+}
+
+/// Reads any range of a seekable file, only decompressing the frames
+/// that hold it
+pub struct SeekableDecoder<R: Read + Seek> {
+    inner: R,
+    table: SeekTable,
+    pos: u64,
+    // The last frame that was decompressed
+    frame: Option<(usize, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> SeekableDecoder<R> {
+    /// Instantiates a new `SeekableDecoder` positioned at the start of the content
+    pub fn new(mut inner: R) -> Result<SeekableDecoder<R>> {
+        let table = SeekTable::read_from(&mut inner)?;
+        Ok(SeekableDecoder {
+            inner,
+            table,
+            pos: 0,
+            frame: None,
+        })
+    }
+
+    /// Returns the seek table
+    pub fn table(&self) -> &SeekTable {
+        &self.table
+    }
+
+    /// Returns the underlying reader
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // Decompresses a frame unless it is the one already held. The table
+    // and the frame's own header must agree on its size before anything
+    // is decoded, and nothing is allocated up front from either
+    fn load(&mut self, index: usize) -> Result<()> {
+        if self.frame.as_ref().is_none_or(|(loaded, _)| *loaded != index) {
+            let frame = self.table.frames[index];
+            self.inner.seek(SeekFrom::Start(frame.compressed_offset))?;
+            let header = Header::read_from(&mut (&mut self.inner).take(frame.compressed_size))?;
+            if header.is_streamed() || header.original_size != frame.size {
+                return Err(invalid("Frame size does not match the seek table"));
+            }
+
+            self.inner.seek(SeekFrom::Start(frame.compressed_offset))?;
+            let mut data = Vec::new();
+            container::decompress(&mut (&mut self.inner).take(frame.compressed_size), &mut data)?;
+            if data.len() as u64 != frame.size {
+                return Err(invalid("Frame size does not match the seek table"));
+            }
+            self.frame = Some((index, data));
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let index = match self.table.find(self.pos) {
+            Some(index) => index,
+            None => return Ok(0),
+        };
+
+        let start = (self.pos - self.table.frames[index].offset) as usize;
+        self.load(index)?;
+        let Some((_, data)) = &self.frame else {
+            return Ok(0);
+        };
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableDecoder<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => {
+                self.pos = offset;
+                return Ok(offset);
+            },
+            SeekFrom::End(offset) => (self.table.len(), offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+
+        self.pos = base.checked_add_signed(offset)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "Seek to a negative or overflowing position"))?;
+        Ok(self.pos)
+    }
+}
+
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes).map_err(|e| truncated(e.into()))?;
+    Ok(bytes)
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::Corrupt(msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::path::Path;
+
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lzss.rs")).unwrap()
+    }
+
+    fn seekable(data: &[u8], algorithm: Algorithm, frame_size: usize) -> SeekableDecoder<Cursor<Vec<u8>>> {
+        let mut compressed = Vec::new();
+        compress(&mut &data[..], &mut compressed, algorithm, frame_size).unwrap();
+        SeekableDecoder::new(Cursor::new(compressed)).unwrap()
+    }
+
+    #[test]
+    fn test_read_all() {
+        let data = sample();
+        for algorithm in [Algorithm::Lzss, Algorithm::Huffman] {
+            let mut decoder = seekable(&data, algorithm, 1000);
+            assert_eq!(decoder.table().frames().len(), data.len().div_ceil(1000));
+            assert_eq!(decoder.table().len(), data.len() as u64);
+
+            let mut output = Vec::new();
+            decoder.read_to_end(&mut output).unwrap();
+            assert_eq!(output, data);
+        }
+    }
+
+    #[test]
+    fn test_random_access() {
+        let data = sample();
+        let mut decoder = seekable(&data, Algorithm::Lzss, 777);
+
+        let mut state = 12345u64;
+        for _ in 0..50 {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let start = (state >> 33) as usize % data.len();
+            let len = ((state >> 20) as usize % 2000).min(data.len() - start);
+
+            assert_eq!(decoder.seek(SeekFrom::Start(start as u64)).unwrap(), start as u64);
+            let mut output = vec![0u8; len];
+            decoder.read_exact(&mut output).unwrap();
+            assert_eq!(output, &data[start..start + len]);
+        }
+    }
+
+    #[test]
+    fn test_seek_relative() {
+        let data = sample();
+        let mut decoder = seekable(&data, Algorithm::Huffman, 500);
+
+        let end = decoder.seek(SeekFrom::End(-10)).unwrap();
+        assert_eq!(end, data.len() as u64 - 10);
+        let mut tail = Vec::new();
+        decoder.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, &data[data.len() - 10..]);
+
+        decoder.seek(SeekFrom::Start(600)).unwrap();
+        decoder.seek(SeekFrom::Current(-200)).unwrap();
+        let mut byte = [0u8; 1];
+        decoder.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], data[400]);
+
+        assert_eq!(decoder.seek(SeekFrom::Current(-1000)).unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        // Reading past the end gives nothing
+        decoder.seek(SeekFrom::End(5)).unwrap();
+        assert_eq!(decoder.read(&mut byte).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut decoder = seekable(b"", Algorithm::Lzss, 100);
+        assert!(decoder.table().is_empty());
+
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert!(output.is_empty());
+    }
+
+    // Overwrites the uncompressed size of a frame in the seek table
+    fn set_frame_size(compressed: &mut [u8], index: usize, count: usize, size: u64) {
+        let entry = compressed.len() - FOOTER_SIZE as usize - (count - index) * TABLE_ENTRY_SIZE as usize;
+        compressed[entry + 8..entry + 16].copy_from_slice(&size.to_be_bytes());
+    }
+
+    #[test]
+    fn test_rejects_corrupt_table() {
+        let data = sample();
+        let mut compressed = Vec::new();
+        let count = compress(&mut &data[..], &mut compressed, Algorithm::Lzss, 1000).unwrap().frames().len();
+
+        // A frame claiming far more than it holds fails when read instead of allocating it
+        let mut huge = compressed.clone();
+        set_frame_size(&mut huge, 0, count, 1 << 62);
+        let mut decoder = SeekableDecoder::new(Cursor::new(huge)).unwrap();
+        let err = Error::from(decoder.read_to_end(&mut Vec::new()).unwrap_err());
+        assert!(matches!(err, Error::Corrupt(_)), "{:?}", err);
+
+        // Sizes that add up past u64::MAX
+        let mut overflow = compressed.clone();
+        set_frame_size(&mut overflow, 0, count, u64::MAX - 10);
+        let err = SeekableDecoder::new(Cursor::new(overflow)).err().unwrap();
+        assert!(matches!(err, Error::Corrupt(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_rejects_truncated() {
+        let mut compressed = Vec::new();
+        compress(&mut &sample()[..], &mut compressed, Algorithm::Lzss, 1000).unwrap();
+        compressed.truncate(compressed.len() - 3);
+
+        let err = SeekableDecoder::new(Cursor::new(compressed)).err().unwrap();
+        assert!(matches!(err, Error::Corrupt(_)), "{:?}", err);
+    }
+}
@@ -1,23 +1,25 @@
+#[allow(clippy::module_inception)]
 #[cfg(test)]
 mod tests {
     use std::{fs::File, path::Path};
 
-    use super::*;
-    use crate::{lzss::LZSS, fileops};
+    use crate::{lzss::LZSS, fileops::{self, OutputPolicy}};
 
     #[test]
     fn test() {
-        let mut input = File::open("C:/Users/pleas/Downloads/VSCode/file_compressor/green.txt").unwrap();
-        let path = Path::new("C:/Users/pleas/Downloads/VSCode/file_compressor/out.txt");
-        let mut output = fileops::create_file(path).unwrap();
-        LZSS::write_output(&mut input, &mut output);
+        let mut input = File::open(Path::new(env!("CARGO_MANIFEST_DIR")).join("green.txt")).unwrap();
+        let path = std::env::temp_dir().join("file_compressor_out.txt");
+        let mut output = fileops::create(&path, OutputPolicy::Overwrite).unwrap();
+        LZSS::write_output(&mut input, &mut output).unwrap();
+        output.commit().unwrap();
     }
 
     #[test]
     fn test_1() {
-        let mut input = File::open("C:/Users/pleas/Downloads/VSCode/file_compressor/out.txt").unwrap();
-        let path = Path::new("C:/Users/pleas/Downloads/VSCode/file_compressor/out-decoded.txt");
-        let mut output = fileops::create_file(path).unwrap();
-        LZSS::read_input(&mut input, &mut output);
+        let mut input = File::open(Path::new(env!("CARGO_MANIFEST_DIR")).join("out.txt")).unwrap();
+        let path = std::env::temp_dir().join("file_compressor_out-decoded.txt");
+        let mut output = fileops::create(&path, OutputPolicy::Overwrite).unwrap();
+        LZSS::read_input(&mut input, &mut output).unwrap();
+        output.commit().unwrap();
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,316 @@
+//! Huffman trees and the code tables built from them
+
+use std::io::{Read, Write};
+
+use concat_strs::concat_strs;
+use serde::{Serialize, Deserialize};
+
+use crate::bitreader::BitReader;
+use crate::bitwriter::BitWriter;
+use crate::error::{truncated, Error, Result};
+use crate::heap::Heap;
+use crate::map::{ByteMap, CountMap};
+
+const MAX_NODES: usize = 511;
+
+/// A node of a [`HuffmanTree`]. Children are indices into the tree's arena
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeData {
+    Leaf { byte: u8, freq: usize },
+    Internal { left: u16, right: u16, freq: usize },
+}
+
+impl NodeData {
+    pub fn get_freq(&self) -> usize {
+        match *self {
+            NodeData::Leaf { freq, .. } | NodeData::Internal { freq, .. } => freq,
+        }
+    }
+}
+
+/// A huffman tree stored as a flat arena of nodes
+///
+/// The arena is kept between calls to [`build`](HuffmanTree::build) so one
+/// tree can be reused for every block without reallocating
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HuffmanTree {
+    nodes: Vec<NodeData>,
+    root: Option<u16>,
+    #[serde(skip)]
+    scratch: Vec<(usize, u16)>,
+}
+
+impl HuffmanTree {
+    /// Instantiates an empty `HuffmanTree`
+    pub fn new() -> HuffmanTree {
+        HuffmanTree {
+            nodes: Vec::with_capacity(MAX_NODES),
+            root: None,
+            scratch: Vec::with_capacity(256),
+        }
+    }
+
+    /// Builds a tree from the byte counts
+    pub fn from_counts(counts: &CountMap) -> HuffmanTree {
+        let mut tree = HuffmanTree::new();
+        tree.build(counts);
+        tree
+    }
+
+    /// Replaces the tree with one built from the byte counts
+    ///
+    /// Bytes with a count of zero get no leaf. Ties are broken on the
+    /// position in the arena, so leaves for lower bytes come out first
+    pub fn build(&mut self, counts: &CountMap) {
+        self.nodes.clear();
+        self.root = None;
+
+        let mut items = std::mem::take(&mut self.scratch);
+        items.clear();
+        for (byte, freq) in counts.iter_nonzero() {
+            items.push((freq, self.push(NodeData::Leaf { byte, freq })));
+        }
+
+        let mut heap = Heap::new_min(items);
+        while let Some((freq, left)) = heap.remove() {
+            match heap.remove() {
+                Some((right_freq, right)) => {
+                    let freq = freq + right_freq;
+                    heap.insert((freq, self.push(NodeData::Internal { left, right, freq })));
+                },
+                None => self.root = Some(left),
+            }
+        }
+
+        self.scratch = heap.into_vec();
+    }
+
+    fn push(&mut self, node: NodeData) -> u16 {
+        self.nodes.push(node);
+        (self.nodes.len() - 1) as u16
+    }
+
+    /// Returns the index of the root node
+    pub fn root(&self) -> Option<u16> {
+        self.root
+    }
+
+    /// Returns the node at `idx`
+    pub fn get(&self, idx: u16) -> &NodeData {
+        &self.nodes[idx as usize]
+    }
+
+    /// Returns every node in the arena
+    pub fn nodes(&self) -> &[NodeData] {
+        &self.nodes
+    }
+
+    /// Generates the code of every leaf without recursion
+    ///
+    /// A tree with a single leaf gives it the code `0`
+    pub fn codes(&self) -> ByteMap<String> {
+        let mut table = ByteMap::new();
+        let root = match self.root {
+            Some(root) => root,
+            None => return table,
+        };
+
+        if let NodeData::Leaf { byte, .. } = *self.get(root) {
+            table.insert("0".to_string(), byte);
+            return table;
+        }
+
+        let mut stack = Vec::with_capacity(256);
+        stack.push((root, String::new()));
+        while let Some((idx, code)) = stack.pop() {
+            match *self.get(idx) {
+                NodeData::Leaf { byte, .. } => table.insert(code, byte),
+                NodeData::Internal { left, right, .. } => {
+                    stack.push((right, concat_strs!(code.as_str(), '1')));
+                    stack.push((left, concat_strs!(code.as_str(), '0')));
+                }
+            }
+        }
+        table
+    }
+
+    /// Writes the shape of the tree in pre-order: a `0` bit for an
+    /// internal node, a `1` bit followed by the byte for a leaf
+    ///
+    /// Frequencies are not written. Does nothing for an empty tree
+    pub fn write_to<W: Write>(&self, writer: &mut BitWriter<W>) -> Result<()> {
+        let mut stack: Vec<u16> = self.root.into_iter().collect();
+        while let Some(idx) = stack.pop() {
+            match *self.get(idx) {
+                NodeData::Leaf { byte, .. } => {
+                    writer.write_bit(true)?;
+                    writer.write_u8(byte)?;
+                },
+                NodeData::Internal { left, right, .. } => {
+                    writer.write_bit(false)?;
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a tree written by [`write_to`](HuffmanTree::write_to)
+    ///
+    /// Fails if the data does not describe a valid tree
+    pub fn read_from<R: Read>(reader: &mut BitReader<R>) -> Result<HuffmanTree> {
+        let mut tree = HuffmanTree::new();
+        // Internal nodes still waiting for children and whether their left is set
+        let mut pending: Vec<(u16, bool)> = Vec::new();
+
+        loop {
+            if tree.nodes.len() == MAX_NODES {
+                return Err(Error::Corrupt("Huffman tree has too many nodes".to_string()));
+            }
+
+            let is_leaf = reader.read_bit().map_err(truncated)? == 1;
+            let idx = match is_leaf {
+                true => {
+                    let byte = reader.read_byte().map_err(truncated)?;
+                    tree.push(NodeData::Leaf { byte, freq: 0 })
+                },
+                false => tree.push(NodeData::Internal { left: 0, right: 0, freq: 0 }),
+            };
+
+            match pending.last_mut() {
+                None => tree.root = Some(idx),
+                Some((parent, left_set)) => {
+                    let parent = *parent;
+                    if let NodeData::Internal { left, right, .. } = &mut tree.nodes[parent as usize] {
+                        if *left_set {
+                            *right = idx;
+                            pending.pop();
+                        } else {
+                            *left = idx;
+                            *left_set = true;
+                        }
+                    }
+                }
+            }
+
+            if !is_leaf {
+                pending.push((idx, false));
+            }
+            if pending.is_empty() {
+                return Ok(tree);
+            }
+        }
+    }
+
+    /// Reads bits until a leaf is reached and returns its byte
+    ///
+    /// Fails if EOF or can't read in data
+    pub fn decode_byte<R: Read>(&self, reader: &mut BitReader<R>) -> Result<u8> {
+        let mut idx = self.root
+            .ok_or_else(|| Error::Corrupt("Empty huffman tree".to_string()))?;
+
+        if let NodeData::Leaf { byte, .. } = *self.get(idx) {
+            reader.read_bit().map_err(truncated)?;
+            return Ok(byte);
+        }
+
+        loop {
+            match *self.get(idx) {
+                NodeData::Leaf { byte, .. } => return Ok(byte),
+                NodeData::Internal { left, right, .. } => {
+                    idx = if reader.read_bit().map_err(truncated)? == 0 { left } else { right };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitwriter::BitWriter;
+
+    fn counts(pairs: &[(u8, usize)]) -> CountMap {
+        pairs.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_codes() {
+        let tree = HuffmanTree::from_counts(&counts(&[(b'a', 8), (b'b', 4), (b'c', 2), (b'd', 2)]));
+        let table = tree.codes();
+
+        assert_eq!(tree.nodes().len(), 7);
+        assert_eq!(tree.get(tree.root().unwrap()).get_freq(), 16);
+        assert_eq!(table.get(b'a').as_ref().unwrap().len(), 1);
+        assert_eq!(table.get(b'b').as_ref().unwrap().len(), 2);
+        assert_eq!(table.get(b'c').as_ref().unwrap().len(), 3);
+        assert_eq!(table.get(b'd').as_ref().unwrap().len(), 3);
+        assert_eq!(table.get(b'e'), &None);
+    }
+
+    #[test]
+    fn test_single_and_empty() {
+        let tree = HuffmanTree::from_counts(&counts(&[(b'x', 3)]));
+        assert_eq!(tree.codes().get(b'x'), &Some("0".to_string()));
+
+        let tree = HuffmanTree::from_counts(&CountMap::new());
+        assert_eq!(tree.root(), None);
+        assert_eq!(tree.codes().iter_nonzero().count(), 0);
+    }
+
+    #[test]
+    fn test_reuse_keeps_allocation() {
+        let mut tree = HuffmanTree::from_counts(&(0..=255).map(|byte| (byte, 1)).collect());
+        let ptr = tree.nodes().as_ptr();
+
+        tree.build(&counts(&[(1, 5), (2, 7), (3, 1)]));
+        assert_eq!(tree.nodes().len(), 5);
+        assert_eq!(tree.nodes().as_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_write_and_read_tree() {
+        let tree = HuffmanTree::from_counts(&b"mississippi river".iter().map(|byte| (*byte, 1)).collect());
+
+        let mut encoded = Vec::new();
+        {
+            let mut writer = BitWriter::new(&mut encoded);
+            tree.write_to(&mut writer).unwrap();
+            writer.flush().unwrap();
+        }
+
+        let read = HuffmanTree::read_from(&mut BitReader::new(&encoded[..])).unwrap();
+        assert_eq!(read.codes().iter_nonzero().collect::<Vec<_>>(), tree.codes().iter_nonzero().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_read_truncated_tree() {
+        let encoded = [0b0000_0000u8];
+        assert!(HuffmanTree::read_from(&mut BitReader::new(&encoded[..])).is_err());
+    }
+
+    #[test]
+    fn test_decode_byte() {
+        let input = b"abracadabra";
+        let tree = HuffmanTree::from_counts(&input.iter().map(|byte| (*byte, 1)).collect());
+        let table = tree.codes();
+
+        let mut encoded = Vec::new();
+        {
+            let mut writer = BitWriter::new(&mut encoded);
+            for byte in input {
+                for bit in table.get(*byte).as_ref().unwrap().chars() {
+                    writer.write_bit(bit == '1').unwrap();
+                }
+            }
+            writer.flush().unwrap();
+        }
+
+        let mut reader = BitReader::new(&encoded[..]);
+        let decoded: Vec<u8> = (0..input.len())
+            .map(|_| tree.decode_byte(&mut reader).unwrap())
+            .collect();
+        assert_eq!(decoded, input);
+    }
+}
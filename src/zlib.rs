@@ -0,0 +1,183 @@
+//! zlib streams (RFC 1950): DEFLATE data between a two byte header and an
+//! Adler-32 of what it decodes to, as sent with HTTP's `deflate` encoding
+
+use std::io::{self, Read, Write};
+
+use crate::checksum::{Adler32, ChecksumError};
+use crate::deflate::Deflate;
+use crate::error::{truncated, Error, Result};
+use crate::lzss;
+
+// DEFLATE with a 32K window, the only method
+const METHOD_DEFLATE: u8 = 8;
+const MAX_WINDOW_BITS: u8 = 15;
+
+const FLAG_DICTIONARY: u8 = 0b0010_0000;
+
+/// Returns true if the first two bytes are a zlib header: DEFLATE, a
+/// window zlib allows and a check value that works out
+pub fn is_header(bytes: &[u8]) -> bool {
+    match *bytes {
+        [method, flags, ..] => {
+            method & 0x0F == METHOD_DEFLATE
+                && (method >> 4) + 8 <= MAX_WINDOW_BITS
+                && u16::from_be_bytes([method, flags]) % 31 == 0
+        },
+        _ => false,
+    }
+}
+
+/// Compresses everything in the reader into a zlib stream
+///
+/// `level` goes from [`lzss::MIN_LEVEL`] to [`lzss::MAX_LEVEL`] and is
+/// recorded in the header the way zlib does
+pub fn compress<R: Read, W: Write>(reader: &mut R, writer: &mut W, level: u8) -> Result<()> {
+    let method = (MAX_WINDOW_BITS - 8) << 4 | METHOD_DEFLATE;
+    let compression = match level {
+        lzss::MIN_LEVEL => 0,
+        2..=5 => 1,
+        6 => 2,
+        _ => 3,
+    };
+    let flags = compression << 6;
+    // The check bits make the header a multiple of 31
+    let check = (31 - u16::from_be_bytes([method, flags]) % 31) % 31;
+    writer.write_all(&[method, flags | check as u8])?;
+
+    let mut reader = AdlerReader { inner: reader, adler: Adler32::new() };
+    Deflate::write_output_level(&mut reader, writer, level)?;
+    writer.write_all(&reader.adler.finish().to_be_bytes())?;
+    Ok(())
+}
+
+/// Decodes a zlib stream and returns the decoded size
+///
+/// Fails with [`Error::Unsupported`] for streams that need a preset
+/// dictionary, and with [`Error::ChecksumMismatch`] if the data does not
+/// match its Adler-32, after it has been written
+pub fn decompress<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<u64> {
+    let header: [u8; 2] = read_array(reader)?;
+    if !is_header(&header) {
+        return Err(Error::Corrupt("Not a zlib stream (bad header)".to_string()));
+    }
+    if header[1] & FLAG_DICTIONARY != 0 {
+        return Err(Error::Unsupported("zlib streams with a preset dictionary are not supported".to_string()));
+    }
+
+    let mut output = AdlerWriter { inner: writer, adler: Adler32::new(), written: 0 };
+    Deflate::read_input(reader, &mut output)?;
+    output.flush()?;
+
+    let expected = u32::from_be_bytes(read_array(reader)?);
+    let actual = output.adler.finish();
+    if expected != actual {
+        return Err(ChecksumError { block: None, expected, actual }.into());
+    }
+    Ok(output.written)
+}
+
+// Everything read here is part of the format, so running out is corruption
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes).map_err(|e| truncated(e.into()))?;
+    Ok(bytes)
+}
+
+// Passes reads through while keeping an Adler-32 of them
+struct AdlerReader<R> {
+    inner: R,
+    adler: Adler32,
+}
+
+impl<R: Read> Read for AdlerReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.adler.update(&buf[..len]);
+        Ok(len)
+    }
+}
+
+// Passes writes through while keeping an Adler-32 and a count of them
+struct AdlerWriter<W> {
+    inner: W,
+    adler: Adler32,
+    written: u64,
+}
+
+impl<W: Write> Write for AdlerWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.adler.update(&buf[..len]);
+        self.written += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn root() -> &'static Path {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+    }
+
+    fn inflate(compressed: &[u8]) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        let size = decompress(&mut &compressed[..], &mut output)?;
+        assert_eq!(size, output.len() as u64);
+        Ok(output)
+    }
+
+    #[test]
+    fn test_zlib_vectors() {
+        let vectors = [("green.zlib", "green.txt"), ("words.zlib", "testdata/deflate/words.txt")];
+        for (name, plain) in vectors {
+            let compressed = std::fs::read(root().join("testdata/deflate").join(name)).unwrap();
+            assert!(is_header(&compressed));
+            assert_eq!(inflate(&compressed).unwrap(), std::fs::read(root().join(plain)).unwrap(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = std::fs::read(root().join("green.txt")).unwrap();
+        for level in lzss::MIN_LEVEL..=lzss::MAX_LEVEL {
+            let mut compressed = Vec::new();
+            compress(&mut &data[..], &mut compressed, level).unwrap();
+            assert!(is_header(&compressed), "{}", level);
+            assert_eq!(inflate(&compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_headers() {
+        // What zlib writes at its default, fastest and best levels, and with a smaller window
+        for header in [[0x78, 0x9c], [0x78, 0x01], [0x78, 0xda], [0x58, 0x85]] {
+            assert!(is_header(&header), "{:x?}", header);
+        }
+        assert!(!is_header(&[0x78, 0x9d]));
+        assert!(!is_header(b"FCMP"));
+        assert!(!is_header(&[0x1f, 0x8b]));
+        assert!(!is_header(&[0x78]));
+    }
+
+    #[test]
+    fn test_rejects_bad_streams() {
+        let compressed = std::fs::read(root().join("testdata/deflate/green.zlib")).unwrap();
+
+        let mut bad_adler = compressed.clone();
+        *bad_adler.last_mut().unwrap() ^= 1;
+        assert!(matches!(inflate(&bad_adler), Err(Error::ChecksumMismatch(_))));
+
+        // A valid header that asks for a preset dictionary
+        assert!(matches!(inflate(&[0x78, 0xbb, 0, 0, 0, 1]), Err(Error::Unsupported(_))));
+        assert!(matches!(inflate(&[0x78, 0x9d, 3, 0]), Err(Error::Corrupt(_))));
+        assert!(matches!(inflate(&compressed[..compressed.len() - 2]), Err(Error::Corrupt(_))));
+    }
+}